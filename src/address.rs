@@ -0,0 +1,119 @@
+use crate::decode::Decode;
+use mailparse::{addrparse, MailAddr, SingleInfo};
+use std::fmt;
+
+/// A single mailbox from an address header, split into its display name,
+/// local part and domain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Address {
+    pub display_name: Option<String>,
+    pub local_part: String,
+    /// Lowercased domain, `None` for addresses without one.
+    pub domain: Option<String>,
+    /// Name of the RFC 5322 group the address was listed in, if any.
+    pub group: Option<String>,
+}
+
+impl Address {
+    /// Parses an address list header value. `raw` must still contain its
+    /// encoded-words, display names are decoded after the list is split so an
+    /// encoded comma or `@` can't change the structure.
+    pub fn parse_list(raw: &str) -> Vec<Address> {
+        let list = match addrparse(raw) {
+            Ok(list) => list,
+            Err(_) => return Self::parse_lenient(raw),
+        };
+
+        let mut addresses = Vec::new();
+        for entry in list.iter() {
+            match entry {
+                MailAddr::Single(info) => addresses.push(Self::from_info(info, None)),
+                MailAddr::Group(group) => {
+                    let name = Decode::encoded_words(&group.group_name);
+                    for info in &group.addrs {
+                        addresses.push(Self::from_info(info, Some(name.to_owned())));
+                    }
+                }
+            }
+        }
+        addresses
+    }
+
+    pub fn addr_spec(&self) -> String {
+        match &self.domain {
+            Some(domain) => format!("{}@{}", self.local_part, domain),
+            None => self.local_part.to_owned(),
+        }
+    }
+
+    fn from_info(info: &SingleInfo, group: Option<String>) -> Self {
+        let (local_part, domain) = Self::split_addr(&info.addr);
+        Self {
+            display_name: info
+                .display_name
+                .as_deref()
+                .map(Decode::encoded_words)
+                .filter(|n| !n.trim().is_empty()),
+            local_part,
+            domain,
+            group,
+        }
+    }
+
+    /// The domain follows the last `@`, quoted local parts may contain one.
+    fn split_addr(addr: &str) -> (String, Option<String>) {
+        let addr = addr.trim().trim_start_matches('<').trim_end_matches('>');
+        match addr.rfind('@') {
+            Some(at) => (
+                addr[..at].to_string(),
+                Some(addr[at + 1..].trim().to_ascii_lowercase()),
+            ),
+            None => (addr.to_string(), None),
+        }
+    }
+
+    /// Fallback for headers that are not valid RFC 5322, which is common in
+    /// spam. Takes every `<...>` address, or every `@` token when there are no
+    /// angle brackets.
+    fn parse_lenient(raw: &str) -> Vec<Address> {
+        let mut addresses = Vec::new();
+        let mut rest = raw;
+        while let (Some(open), Some(close)) = (rest.find('<'), rest.find('>')) {
+            if close < open {
+                rest = &rest[close + 1..];
+                continue;
+            }
+            let name = rest[..open].trim().trim_matches(|c| c == '"' || c == ',');
+            let (local_part, domain) = Self::split_addr(&rest[open + 1..close]);
+            addresses.push(Address {
+                display_name: (!name.trim().is_empty()).then(|| Decode::encoded_words(name.trim())),
+                local_part,
+                domain,
+                group: None,
+            });
+            rest = &rest[close + 1..];
+        }
+
+        if addresses.is_empty() {
+            for token in raw.split([',', ';', ' ']).filter(|t| t.contains('@')) {
+                let (local_part, domain) = Self::split_addr(token.trim_matches('"'));
+                addresses.push(Address {
+                    display_name: None,
+                    local_part,
+                    domain,
+                    group: None,
+                });
+            }
+        }
+        addresses
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.display_name {
+            Some(name) => write!(f, "\"{}\" <{}>", name, self.addr_spec()),
+            None => write!(f, "{}", self.addr_spec()),
+        }
+    }
+}
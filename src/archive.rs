@@ -0,0 +1,502 @@
+use crate::{filetype::FileType, hashes::Hashes};
+use flate2::read::GzDecoder;
+use sevenz_rust::{Archive as SevenZArchive, BlockDecoder, SevenZMethod};
+use std::io::{Cursor, Read};
+use zip::ZipArchive;
+
+/// One file found inside an archive attachment. Nested archives are opened
+/// too, their entries are named `outer.zip/inner.zip/file.exe`.
+#[derive(Debug, Clone)]
+pub struct ArchiveEntry {
+    pub name: String,
+    /// 1 for entries of the attachment itself, 2 for entries of an archive
+    /// inside it, and so on.
+    pub depth: usize,
+    /// Uncompressed size as recorded in the archive.
+    pub size: u64,
+    /// `None` when the entry is encrypted or was too large to read.
+    pub hashes: Option<Hashes>,
+    pub encrypted: bool,
+    /// Set for extensions that are rarely sent for legitimate reasons, such
+    /// as scripts, shortcuts, disk images and executables.
+    pub suspicious: bool,
+}
+
+/// The contents of a zip, gzip, tar or 7z attachment. Depth, entry count and
+/// size limits keep zip bombs from exhausting memory, anything skipped
+/// because of them is noted in `warnings`.
+#[derive(Debug, Clone)]
+pub struct ArchiveListing {
+    pub format: &'static str,
+    pub entries: Vec<ArchiveEntry>,
+    pub warnings: Vec<String>,
+}
+
+impl ArchiveListing {
+    /// Lists `data` when it is a supported archive, `None` otherwise.
+    pub fn inspect(data: &[u8]) -> Option<ArchiveListing> {
+        let format = Self::format(data)?;
+        let mut listing = ArchiveListing {
+            format: format.name,
+            entries: Vec::new(),
+            warnings: Vec::new(),
+        };
+        let mut budget = Self::MAX_TOTAL_SIZE;
+        listing.walk(data, format, "", 1, &mut budget);
+        Some(listing)
+    }
+
+    pub fn has_suspicious(&self) -> bool {
+        self.entries.iter().any(|e| e.suspicious)
+    }
+
+    pub fn has_encrypted(&self) -> bool {
+        self.entries.iter().any(|e| e.encrypted)
+    }
+
+    fn format(data: &[u8]) -> Option<&'static FileType> {
+        FileType::detect(data).filter(|t| Self::FORMATS.contains(t))
+    }
+
+    fn walk(
+        &mut self,
+        data: &[u8],
+        format: &FileType,
+        prefix: &str,
+        depth: usize,
+        budget: &mut u64,
+    ) {
+        let found = if *format == FileType::GZIP {
+            self.gzip_entries(data, prefix, budget)
+        } else if *format == FileType::TAR {
+            self.tar_entries(data, prefix, budget)
+        } else if *format == FileType::SEVEN_ZIP {
+            self.seven_zip_entries(data, prefix, budget)
+        } else {
+            self.zip_entries(data, prefix, budget)
+        };
+
+        for (name, size, encrypted, content) in found {
+            if self.entries.len() >= Self::MAX_ENTRIES {
+                self.warnings.push(format!(
+                    "listing stopped after {} entries",
+                    Self::MAX_ENTRIES
+                ));
+                return;
+            }
+
+            let name = format!("{prefix}{name}");
+            self.entries.push(ArchiveEntry {
+                suspicious: Self::is_suspicious(&name),
+                name: name.to_owned(),
+                depth,
+                size,
+                hashes: content.as_deref().map(Hashes::of),
+                encrypted,
+            });
+
+            let inner = content
+                .as_deref()
+                .and_then(|c| Self::format(c).map(|f| (c, f)));
+            if let Some((content, inner)) = inner {
+                if depth >= Self::MAX_DEPTH {
+                    self.warnings.push(format!(
+                        "{name}: nested archive not opened, depth limit is {}",
+                        Self::MAX_DEPTH
+                    ));
+                } else {
+                    self.walk(content, inner, &format!("{name}/"), depth + 1, budget);
+                }
+            }
+        }
+    }
+
+    /// Entries as `(name, size, encrypted, content)`, directories left out.
+    /// Each reader returns one entry past `MAX_ENTRIES` so that `walk` can
+    /// tell a full listing from a cut one.
+    fn zip_entries(&mut self, data: &[u8], prefix: &str, budget: &mut u64) -> Vec<Found> {
+        let mut zip = match ZipArchive::new(Cursor::new(data)) {
+            Ok(zip) => zip,
+            Err(err) => {
+                self.warnings
+                    .push(format!("{}: zip not readable, {err}", Self::label(prefix)));
+                return Vec::new();
+            }
+        };
+
+        let mut found = Vec::new();
+        for i in 0..zip.len().min(Self::MAX_ENTRIES + 1) {
+            // The raw entry gives the metadata without trying to decrypt.
+            let (name, size, encrypted) = match zip.by_index_raw(i) {
+                Ok(file) if file.is_dir() => continue,
+                Ok(file) => (file.name().to_string(), file.size(), file.encrypted()),
+                Err(err) => {
+                    self.warnings.push(format!("{prefix}entry {i}: {err}"));
+                    continue;
+                }
+            };
+            let content = if encrypted {
+                None
+            } else {
+                match zip.by_index(i) {
+                    Ok(mut file) => {
+                        self.read_bounded(&mut file, &format!("{prefix}{name}"), budget)
+                    }
+                    Err(err) => {
+                        self.warnings.push(format!("{prefix}{name}: {err}"));
+                        None
+                    }
+                }
+            };
+            found.push((name, size, encrypted, content));
+        }
+        found
+    }
+
+    fn gzip_entries(&mut self, data: &[u8], prefix: &str, budget: &mut u64) -> Vec<Found> {
+        let mut decoder = GzDecoder::new(data);
+        let content = self.read_bounded(&mut decoder, &format!("{prefix}(gzip content)"), budget);
+        let name = decoder
+            .header()
+            .and_then(|h| h.filename())
+            .map(|f| String::from_utf8_lossy(f).into_owned())
+            .unwrap_or_else(|| String::from("(gzip content)"));
+        // ISIZE in the trailer holds the uncompressed size modulo 2^32.
+        let size = data
+            .len()
+            .checked_sub(4)
+            .and_then(|start| data[start..].try_into().ok())
+            .map_or(0, |b: [u8; 4]| u64::from(u32::from_le_bytes(b)));
+        vec![(name, size, false, content)]
+    }
+
+    fn tar_entries(&mut self, data: &[u8], prefix: &str, budget: &mut u64) -> Vec<Found> {
+        let mut archive = tar::Archive::new(Cursor::new(data));
+        let entries = match archive.entries() {
+            Ok(entries) => entries,
+            Err(err) => {
+                self.warnings
+                    .push(format!("{}: tar not readable, {err}", Self::label(prefix)));
+                return Vec::new();
+            }
+        };
+
+        let mut found = Vec::new();
+        for entry in entries.take(Self::MAX_ENTRIES + 1) {
+            let mut entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    self.warnings
+                        .push(format!("{}: {err}", Self::label(prefix)));
+                    break;
+                }
+            };
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let name = entry.path_bytes().into_owned();
+            let name = String::from_utf8_lossy(&name).into_owned();
+            let size = entry.size();
+            let content = self.read_bounded(&mut entry, &format!("{prefix}{name}"), budget);
+            found.push((name, size, false, content));
+        }
+        found
+    }
+
+    fn seven_zip_entries(&mut self, data: &[u8], prefix: &str, budget: &mut u64) -> Vec<Found> {
+        let mut source = Cursor::new(data);
+        let archive = match SevenZArchive::read(&mut source, data.len() as u64, &[]) {
+            Ok(archive) => archive,
+            Err(sevenz_rust::Error::PasswordRequired) => {
+                self.warnings.push(format!(
+                    "{}: 7z headers are encrypted, names are hidden",
+                    Self::label(prefix)
+                ));
+                return vec![(String::from("(encrypted headers)"), 0, true, None)];
+            }
+            Err(err) => {
+                self.warnings
+                    .push(format!("{}: 7z not readable, {err}", Self::label(prefix)));
+                return Vec::new();
+            }
+        };
+
+        let encrypted_folder: Vec<bool> = archive
+            .folders
+            .iter()
+            .map(|f| {
+                f.coders
+                    .iter()
+                    .any(|c| c.decompression_method_id() == SevenZMethod::ID_AES256SHA256)
+            })
+            .collect();
+
+        // Entries of a solid folder can only be read in order, so a folder is
+        // abandoned as soon as one of its entries goes over the limits.
+        let mut contents: Vec<Option<Vec<u8>>> = vec![None; archive.files.len()];
+        for (folder, encrypted) in encrypted_folder.iter().enumerate() {
+            if *encrypted {
+                continue;
+            }
+            let first = archive.stream_map.folder_first_file_index[folder];
+            let mut index = first;
+            let mut warnings = Vec::new();
+            let decoder = BlockDecoder::new(folder, &archive, &[], &mut source);
+            let result = decoder.for_each_entries(&mut |entry, reader| {
+                let content = Self::read_limited(reader, budget)
+                    .map_err(|err| warnings.push(format!("{prefix}{}: {err}", entry.name())));
+                let read = content.is_ok();
+                contents[index] = content.ok();
+                index += 1;
+                Ok(read)
+            });
+            if let Err(err) = result {
+                warnings.push(format!(
+                    "{}: 7z folder {folder} not readable, {err}",
+                    Self::label(prefix)
+                ));
+            }
+            self.warnings.extend(warnings);
+        }
+
+        archive
+            .files
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| !f.is_directory)
+            .take(Self::MAX_ENTRIES + 1)
+            .map(|(i, f)| {
+                let encrypted = archive.stream_map.file_folder_index[i]
+                    .is_some_and(|folder| encrypted_folder[folder]);
+                let content = if f.has_stream {
+                    contents[i].take()
+                } else {
+                    Some(Vec::new())
+                };
+                (f.name().to_string(), f.size(), encrypted, content)
+            })
+            .collect()
+    }
+
+    /// Reads one entry, noting it in the warnings when it is over the limits.
+    fn read_bounded(
+        &mut self,
+        reader: &mut dyn Read,
+        name: &str,
+        budget: &mut u64,
+    ) -> Option<Vec<u8>> {
+        Self::read_limited(reader, budget)
+            .map_err(|err| self.warnings.push(format!("{name}: {err}")))
+            .ok()
+    }
+
+    fn read_limited(reader: &mut dyn Read, budget: &mut u64) -> Result<Vec<u8>, String> {
+        let limit = Self::MAX_ENTRY_SIZE.min(*budget);
+        let mut content = Vec::new();
+        reader
+            .take(limit + 1)
+            .read_to_end(&mut content)
+            .map_err(|err| err.to_string())?;
+        if content.len() as u64 > limit {
+            return Err(format!("larger than {limit} bytes, not read"));
+        }
+        *budget -= content.len() as u64;
+        Ok(content)
+    }
+
+    /// Name of the archive a `prefix` belongs to, for warnings.
+    fn label(prefix: &str) -> &str {
+        match prefix.trim_end_matches('/') {
+            "" => "attachment",
+            name => name,
+        }
+    }
+
+    fn is_suspicious(name: &str) -> bool {
+        let name = name.trim_end_matches(['.', ' ']).to_ascii_lowercase();
+        name.rsplit_once('.')
+            .is_some_and(|(_, ext)| Self::SUSPICIOUS_EXTENSIONS.contains(&ext))
+    }
+}
+
+/// An entry as read from one archive: name, size, encrypted flag and content.
+type Found = (String, u64, bool, Option<Vec<u8>>);
+
+impl ArchiveListing {
+    const MAX_DEPTH: usize = 3;
+    const MAX_ENTRIES: usize = 1000;
+    const MAX_ENTRY_SIZE: u64 = 50 * 1024 * 1024;
+    const MAX_TOTAL_SIZE: u64 = 200 * 1024 * 1024;
+
+    const FORMATS: [&'static FileType; 5] = [
+        &FileType::ZIP,
+        &FileType::JAR,
+        &FileType::GZIP,
+        &FileType::TAR,
+        &FileType::SEVEN_ZIP,
+    ];
+
+    const SUSPICIOUS_EXTENSIONS: [&'static str; 30] = [
+        "js", "jse", "vbs", "vbe", "wsf", "wsh", "hta", "ps1", "bat", "cmd", "lnk", "url", "iso",
+        "img", "vhd", "vhdx", "exe", "scr", "com", "pif", "msi", "dll", "cpl", "jar", "chm", "reg",
+        "one", "xll", "docm", "xlsm",
+    ];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+    use zip::{write::SimpleFileOptions, AesMode, CompressionMethod, ZipWriter};
+
+    fn zip(entries: &[(&str, &[u8])], options: SimpleFileOptions) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in entries {
+            writer.start_file(*name, options).unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn deflated() -> SimpleFileOptions {
+        SimpleFileOptions::default().compression_method(CompressionMethod::Deflated)
+    }
+
+    #[test]
+    fn nested_archives_stop_at_the_depth_limit() {
+        let mut data = zip(&[("payload.exe", b"MZ")], deflated());
+        for level in (1..=ArchiveListing::MAX_DEPTH).rev() {
+            data = zip(&[(&format!("level{level}.zip"), &data)], deflated());
+        }
+
+        let listing = ArchiveListing::inspect(&data).unwrap();
+        let names: Vec<&str> = listing.entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "level1.zip",
+                "level1.zip/level2.zip",
+                "level1.zip/level2.zip/level3.zip"
+            ]
+        );
+        assert_eq!(listing.entries[2].depth, ArchiveListing::MAX_DEPTH);
+        assert_eq!(
+            listing.warnings,
+            ["level1.zip/level2.zip/level3.zip: nested archive not opened, depth limit is 3"]
+        );
+    }
+
+    #[test]
+    fn listing_stops_at_the_entry_limit() {
+        let names: Vec<String> = (0..=ArchiveListing::MAX_ENTRIES)
+            .map(|i| format!("{i}.txt"))
+            .collect();
+        let entries: Vec<(&str, &[u8])> = names.iter().map(|n| (n.as_str(), &b""[..])).collect();
+        let listing =
+            ArchiveListing::inspect(&zip(&entries, SimpleFileOptions::default())).unwrap();
+        assert_eq!(listing.entries.len(), ArchiveListing::MAX_ENTRIES);
+        assert_eq!(listing.warnings, ["listing stopped after 1000 entries"]);
+    }
+
+    #[test]
+    fn oversized_entries_are_not_read() {
+        let limit = ArchiveListing::MAX_ENTRY_SIZE as usize;
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .start_file("bomb.bin", deflated().compression_level(Some(1)))
+            .unwrap();
+        let chunk = vec![0u8; 1024 * 1024];
+        for _ in 0..limit / chunk.len() {
+            writer.write_all(&chunk).unwrap();
+        }
+        writer.write_all(b"\0").unwrap();
+        writer.start_file("small.txt", deflated()).unwrap();
+        writer.write_all(b"hello").unwrap();
+        let data = writer.finish().unwrap().into_inner();
+
+        let listing = ArchiveListing::inspect(&data).unwrap();
+        assert_eq!(listing.entries[0].size, limit as u64 + 1);
+        assert!(listing.entries[0].hashes.is_none());
+        assert!(listing.entries[1].hashes.is_some());
+        assert_eq!(
+            listing.warnings,
+            [format!("bomb.bin: larger than {limit} bytes, not read")]
+        );
+    }
+
+    #[test]
+    fn total_size_budget_is_shared() {
+        let data = zip(&[("a.txt", b"12345"), ("b.txt", b"67890")], deflated());
+        let format = ArchiveListing::format(&data).unwrap();
+        let mut listing = ArchiveListing {
+            format: format.name,
+            entries: Vec::new(),
+            warnings: Vec::new(),
+        };
+        let mut budget = 8;
+        listing.walk(&data, format, "", 1, &mut budget);
+        assert!(listing.entries[0].hashes.is_some());
+        assert!(listing.entries[1].hashes.is_none());
+        assert_eq!(listing.warnings, ["b.txt: larger than 3 bytes, not read"]);
+    }
+
+    #[test]
+    fn encrypted_entries_are_flagged() {
+        let options = deflated().with_aes_encryption(AesMode::Aes256, "infected");
+        let data = zip(&[("invoice.js", b"WScript.Echo(1)")], options);
+
+        let listing = ArchiveListing::inspect(&data).unwrap();
+        assert!(listing.has_encrypted());
+        assert!(listing.has_suspicious());
+        assert!(listing.entries[0].encrypted);
+        assert!(listing.entries[0].hashes.is_none());
+        assert!(listing.warnings.is_empty());
+    }
+
+    #[test]
+    fn tar_gz_is_opened() {
+        let mut tar = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(2);
+        header.set_mode(0o644);
+        header.set_cksum();
+        tar.append_data(&mut header, "docs/run.bat", &b"@e"[..])
+            .unwrap();
+        let tar = tar.into_inner().unwrap();
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(&tar).unwrap();
+        let data = gzip.finish().unwrap();
+
+        let listing = ArchiveListing::inspect(&data).unwrap();
+        assert_eq!(listing.format, FileType::GZIP.name);
+        let entries: Vec<(&str, usize, bool)> = listing
+            .entries
+            .iter()
+            .map(|e| (e.name.as_str(), e.depth, e.suspicious))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                ("(gzip content)", 1, false),
+                ("(gzip content)/docs/run.bat", 2, true)
+            ]
+        );
+    }
+
+    #[test]
+    fn suspicious_names() {
+        for name in [
+            "a/invoice.pdf.exe",
+            "RUN.BAT",
+            "scan.iso",
+            "x.js. ",
+            "sheet.xlsm",
+        ] {
+            assert!(ArchiveListing::is_suspicious(name), "{name}");
+        }
+        for name in ["invoice.pdf", "exe", "notes.txt", "photo.jpg", "js/"] {
+            assert!(!ArchiveListing::is_suspicious(name), "{name}");
+        }
+    }
+}
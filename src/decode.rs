@@ -0,0 +1,325 @@
+use base64::{
+    alphabet,
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+    Engine,
+};
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+use mailparse::MailHeader;
+use std::collections::BTreeMap;
+
+/// Decoding of RFC 2047 encoded-words and RFC 2231 parameter values.
+///
+/// mailparse decodes each encoded-word on its own and each RFC 2231
+/// continuation on its own, which breaks multi-byte characters that senders
+/// split across words or segments. These helpers join the raw bytes first and
+/// only then apply the charset.
+pub struct Decode;
+
+/// Base64 engine that accepts missing padding and non-zero trailing bits,
+/// both are common in mail produced by broken or hostile senders.
+pub const LENIENT_BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new()
+        .with_decode_allow_trailing_bits(true)
+        .with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// The URL-safe (`-_`) variant of [`LENIENT_BASE64`], used by link rewriters.
+pub const LENIENT_BASE64_URL: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::URL_SAFE,
+    GeneralPurposeConfig::new()
+        .with_decode_allow_trailing_bits(true)
+        .with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+impl Decode {
+    /// Unfolded header value with encoded-words decoded.
+    pub fn header_value(header: &MailHeader) -> String {
+        Self::encoded_words(&Self::header_raw(header))
+    }
+
+    /// Unfolded header value with encoded-words left as they are. Raw 8bit
+    /// values are read as UTF-8 when valid and Windows-1252 otherwise.
+    pub fn header_raw(header: &MailHeader) -> String {
+        let raw = Self::charset_decode(None, header.get_value_raw());
+        let mut unfolded = String::with_capacity(raw.len());
+        for line in raw.split('\n') {
+            unfolded.push_str(line.strip_suffix('\r').unwrap_or(line));
+        }
+        unfolded.trim().to_string()
+    }
+
+    /// Decodes every `=?charset?B|Q?text?=` word in `value`. Whitespace between
+    /// adjacent encoded-words is dropped, and adjacent words in the same
+    /// charset are decoded together.
+    pub fn encoded_words(value: &str) -> String {
+        let mut out = String::new();
+        let mut pending: Option<(String, Vec<u8>)> = None;
+        let mut gap = String::new();
+        let mut rest = value;
+
+        while !rest.is_empty() {
+            let Some(start) = rest.find("=?") else {
+                gap.push_str(rest);
+                break;
+            };
+            // A stray `=?` is plain text, later words are still decoded.
+            let Some(((charset, bytes), len)) = Self::parse_word(&rest[start..]) else {
+                gap.push_str(&rest[..start + 2]);
+                rest = &rest[start + 2..];
+                continue;
+            };
+
+            gap.push_str(&rest[..start]);
+            rest = &rest[start + len..];
+
+            // Only whitespace between two encoded-words is insignificant.
+            let joinable = gap.trim().is_empty();
+            match pending.take() {
+                Some((prev, mut prev_bytes)) if joinable && prev.eq_ignore_ascii_case(&charset) => {
+                    prev_bytes.extend(bytes);
+                    pending = Some((prev, prev_bytes));
+                }
+                Some((prev, prev_bytes)) => {
+                    out.push_str(&Self::charset_decode(Some(&prev), &prev_bytes));
+                    if !joinable {
+                        out.push_str(&gap);
+                    }
+                    pending = Some((charset, bytes));
+                }
+                None => {
+                    out.push_str(&gap);
+                    pending = Some((charset, bytes));
+                }
+            }
+            gap.clear();
+        }
+
+        if let Some((charset, bytes)) = pending {
+            out.push_str(&Self::charset_decode(Some(&charset), &bytes));
+        }
+        out.push_str(&gap);
+        out
+    }
+
+    /// Parses one encoded-word at the start of `s`, returning the charset, the
+    /// decoded bytes and the length consumed.
+    fn parse_word(s: &str) -> Option<((String, Vec<u8>), usize)> {
+        let inner = s.strip_prefix("=?")?;
+        let q1 = inner.find('?')?;
+        let charset = &inner[..q1];
+        let after = &inner[q1 + 1..];
+        let q2 = after.find('?')?;
+        let encoding = &after[..q2];
+        let text_start = &after[q2 + 1..];
+        let end = text_start.find("?=")?;
+        let text = &text_start[..end];
+
+        if charset.is_empty() || text.contains(char::is_whitespace) {
+            return None;
+        }
+
+        let bytes = match encoding {
+            "B" | "b" => LENIENT_BASE64.decode(text.trim_end_matches('=')).ok()?,
+            "Q" | "q" => Self::percent_like(&text.replace('_', " "), b'='),
+            _ => return None,
+        };
+
+        // RFC 2231 allows a language suffix on the charset, `utf-8*en`.
+        let charset = charset.split('*').next().unwrap_or(charset).to_string();
+        let len = 2 + q1 + 1 + q2 + 1 + end + 2;
+        Some(((charset, bytes), len))
+    }
+
+    /// Splits a parameterized header value such as Content-Type into its main
+    /// value and parameters. Parameter names are lowercased, quoted values may
+    /// contain `;`, and RFC 2231 extended and continued values are joined and
+    /// decoded.
+    pub fn params(value: &str) -> (String, BTreeMap<String, String>) {
+        let mut tokens = Self::split_params(value).into_iter();
+        let main = tokens.next().unwrap_or_default().trim().to_string();
+
+        let mut plain = BTreeMap::<String, String>::new();
+        // name -> (charset, segments by index with their extended flag)
+        let mut extended =
+            BTreeMap::<String, (Option<String>, BTreeMap<u32, (bool, String)>)>::new();
+
+        for token in tokens {
+            let (key, raw) = match token.split_once('=') {
+                Some((k, v)) => (k.trim().to_ascii_lowercase(), v.trim()),
+                None => continue,
+            };
+            let value = Self::unquote(raw);
+
+            let (name, index, is_extended) = match key.split_once('*') {
+                None => {
+                    plain.insert(key, value);
+                    continue;
+                }
+                Some((name, "")) => (name.to_string(), 0, true),
+                Some((name, suffix)) => {
+                    let is_extended = suffix.ends_with('*');
+                    let index = suffix.trim_end_matches('*').parse::<u32>().unwrap_or(0);
+                    (name.to_string(), index, is_extended)
+                }
+            };
+
+            let entry = extended.entry(name).or_default();
+            let mut segment = value;
+            if is_extended && index == 0 {
+                let mut parts = segment.splitn(3, '\'');
+                if let (Some(cs), Some(_lang), Some(text)) =
+                    (parts.next(), parts.next(), parts.next())
+                {
+                    if !cs.is_empty() {
+                        entry.0 = Some(cs.to_string());
+                    }
+                    segment = text.to_string();
+                }
+            }
+            entry.1.insert(index, (is_extended, segment));
+        }
+
+        let mut params = BTreeMap::new();
+        for (name, value) in plain {
+            params.insert(name, Self::encoded_words(&value));
+        }
+        for (name, (charset, segments)) in extended {
+            let mut bytes = Vec::new();
+            for (is_extended, segment) in segments.values() {
+                if *is_extended {
+                    bytes.extend(Self::percent_like(segment, b'%'));
+                } else {
+                    bytes.extend_from_slice(segment.as_bytes());
+                }
+            }
+            // An extended value takes precedence over a plain one of the
+            // same name, it is the one the sender meant to be read.
+            params.insert(name, Self::charset_decode(charset.as_deref(), &bytes));
+        }
+        (main, params)
+    }
+
+    fn split_params(value: &str) -> Vec<String> {
+        let mut tokens = Vec::new();
+        let mut current = String::new();
+        let mut in_quotes = false;
+        let mut escaped = false;
+
+        for c in value.chars() {
+            if escaped {
+                current.push(c);
+                escaped = false;
+                continue;
+            }
+            match c {
+                '\\' if in_quotes => {
+                    current.push(c);
+                    escaped = true;
+                }
+                '"' => {
+                    in_quotes = !in_quotes;
+                    current.push(c);
+                }
+                ';' if !in_quotes => tokens.push(std::mem::take(&mut current)),
+                _ => current.push(c),
+            }
+        }
+        tokens.push(current);
+        tokens
+    }
+
+    fn unquote(raw: &str) -> String {
+        match raw.strip_prefix('"').and_then(|r| r.strip_suffix('"')) {
+            Some(inner) => {
+                let mut out = String::with_capacity(inner.len());
+                let mut chars = inner.chars();
+                while let Some(c) = chars.next() {
+                    if c == '\\' {
+                        if let Some(next) = chars.next() {
+                            out.push(next);
+                        }
+                    } else {
+                        out.push(c);
+                    }
+                }
+                out
+            }
+            None => raw.to_string(),
+        }
+    }
+
+    /// Decodes `<marker>XX` hex escapes, used for both Q encoding (`=`) and
+    /// RFC 2231 percent encoding (`%`).
+    pub(crate) fn percent_like(text: &str, marker: u8) -> Vec<u8> {
+        let bytes = text.as_bytes();
+        let mut out = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] == marker && i + 2 < bytes.len() {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                if let Some(b) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                    out.push(b);
+                    i += 3;
+                    continue;
+                }
+            }
+            out.push(bytes[i]);
+            i += 1;
+        }
+        out
+    }
+
+    /// Decodes bytes with the named charset. Unknown or missing charsets fall
+    /// back to UTF-8 when the bytes are valid UTF-8 and Windows-1252 otherwise,
+    /// so nothing is ever dropped.
+    pub fn charset_decode(charset: Option<&str>, bytes: &[u8]) -> String {
+        let encoding = charset
+            .and_then(|c| Encoding::for_label(c.trim().as_bytes()))
+            .unwrap_or_else(|| {
+                if std::str::from_utf8(bytes).is_ok() {
+                    UTF_8
+                } else {
+                    WINDOWS_1252
+                }
+            });
+        encoding.decode_without_bom_handling(bytes).0.into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoded_words_after_stray_marker() {
+        assert_eq!(
+            Decode::encoded_words("Price =? now =?UTF-8?B?w6k=?="),
+            "Price =? now é"
+        );
+        assert_eq!(Decode::encoded_words("a =?b c"), "a =?b c");
+    }
+
+    #[test]
+    fn encoded_words_joined_across_split_characters() {
+        // "é" split over two words, only valid once the bytes are joined.
+        assert_eq!(
+            Decode::encoded_words("=?UTF-8?Q?caf=C3?= =?UTF-8?Q?=A9_ok?="),
+            "café ok"
+        );
+        assert_eq!(
+            Decode::encoded_words("=?ISO-8859-1?Q?a?= b =?ISO-8859-1?Q?c?="),
+            "a b c"
+        );
+    }
+
+    #[test]
+    fn rfc2231_continuations() {
+        let (main, params) = Decode::params(
+            "attachment; filename*0*=UTF-8''%E2%82; filename*1*=%AC.pdf; size=\"1; 2\"",
+        );
+        assert_eq!(main, "attachment");
+        assert_eq!(params["filename"], "€.pdf");
+        assert_eq!(params["size"], "1; 2");
+    }
+}
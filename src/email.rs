@@ -0,0 +1,392 @@
+use crate::{
+    address::Address,
+    archive::ArchiveListing,
+    filetype::FileType,
+    harvest::HarvestReport,
+    hashes::Hashes,
+    headers::HeaderMap,
+    links::LinkMismatch,
+    macros::MacroReport,
+    mime::MimePart,
+    pdf::PdfReport,
+    qr::QrCode,
+    redirect::RedirectTable,
+    reported::ReportedMessage,
+    smuggling::{SmuggledPayload, Smuggling},
+    urls::{ExtractedUrl, UrlExtractor},
+};
+
+/// A parsed message ready for analysis. When the input was a user report
+/// with the suspicious mail attached, every field describes the attached
+/// mail and `reported` keeps the wrapper details.
+#[derive(Debug, Clone)]
+pub struct ParsedEmail {
+    pub headers: HeaderMap,
+    pub addresses: Addresses,
+    pub mime: MimePart,
+    pub bodies: Vec<Body>,
+    pub attachments: Vec<Attachment>,
+    /// Unique URLs from the bodies, in the order they were found.
+    pub urls: Vec<ExtractedUrl>,
+    /// HTML links whose visible text names a different site than the target.
+    pub link_mismatches: Vec<LinkMismatch>,
+    /// HTML bodies and attachments that build a file in the browser. The
+    /// files recovered from them are listed with the attachments, see
+    /// [`Attachment::recovered`].
+    pub smuggling: Vec<Smuggling>,
+    /// QR codes decoded from the images of the message and its attachments.
+    pub qr_codes: Vec<QrCode>,
+    pub reported: Option<ReportedMessage>,
+    /// Parts that could not be fully decoded, as `part <path>: <problem>`.
+    pub warnings: Vec<String>,
+    /// Set when only a header block was given, the body and attachments are
+    /// unknown rather than absent.
+    pub headers_only: bool,
+}
+
+/// Mailbox lists of the address headers, empty when a header is missing.
+#[derive(Debug, Clone, Default)]
+pub struct Addresses {
+    pub from: Vec<Address>,
+    pub sender: Vec<Address>,
+    pub reply_to: Vec<Address>,
+    pub to: Vec<Address>,
+    pub cc: Vec<Address>,
+}
+
+impl Addresses {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let list = |key: &str| {
+            headers
+                .get_raw(key)
+                .map(Address::parse_list)
+                .unwrap_or_default()
+        };
+        Self {
+            from: list("From"),
+            sender: list("Sender"),
+            reply_to: list("Reply-To"),
+            to: list("To"),
+            cc: list("Cc"),
+        }
+    }
+
+    /// Unique sender domains in the order they appear in From.
+    pub fn from_domains(&self) -> Vec<&str> {
+        let mut domains: Vec<&str> = Vec::new();
+        for domain in self.from.iter().filter_map(|a| a.domain.as_deref()) {
+            if !domains.contains(&domain) {
+                domains.push(domain);
+            }
+        }
+        domains
+    }
+}
+
+/// A text part that is shown as the message body.
+#[derive(Debug, Clone)]
+pub struct Body {
+    pub path: String,
+    pub content_type: String,
+    pub text: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub path: String,
+    pub filename: Option<String>,
+    pub content_type: String,
+    pub disposition: Option<String>,
+    pub size: usize,
+    /// Format recognised from the content, `None` when no signature matched.
+    pub detected_type: Option<&'static FileType>,
+    /// `None` for containers such as attached Outlook items, whose content
+    /// is in their child parts rather than in a body of their own.
+    pub hashes: Option<Hashes>,
+    /// Why the extension, declared type and content disagree, empty when
+    /// they are consistent.
+    pub mismatches: Vec<String>,
+    /// Contents of zip, gzip, tar and 7z attachments.
+    pub archive: Option<ArchiveListing>,
+    /// VBA and XLM macros of Office attachments.
+    pub macros: Option<MacroReport>,
+    /// Scripts, automatic actions and links of PDF attachments.
+    pub pdf: Option<PdfReport>,
+    /// Credential harvesting signs in HTML attachments.
+    pub harvest: Option<HarvestReport>,
+    /// Set for files rebuilt from an HTML smuggling page, which were never
+    /// MIME parts of the message.
+    pub recovered: bool,
+}
+
+impl Attachment {
+    pub fn from_part(part: &MimePart) -> Self {
+        let mut attachment = Self::inspect(
+            &part.path,
+            part.filename.to_owned(),
+            &part.content_type,
+            part.disposition.to_owned(),
+            &part.body,
+        );
+        if part.body.is_empty() && !part.children.is_empty() {
+            attachment.hashes = None;
+        }
+        attachment
+    }
+
+    /// A file recovered from an HTML smuggling page, listed under the path
+    /// of the page it came from.
+    pub fn from_smuggled(path: &str, payload: &SmuggledPayload) -> Self {
+        let mut attachment = Self::inspect(
+            &format!("{path} smuggled"),
+            payload.filename.to_owned(),
+            payload
+                .content_type
+                .as_deref()
+                .unwrap_or("application/octet-stream"),
+            None,
+            &payload.data,
+        );
+        attachment.recovered = true;
+        attachment
+    }
+
+    fn inspect(
+        path: &str,
+        filename: Option<String>,
+        content_type: &str,
+        disposition: Option<String>,
+        body: &[u8],
+    ) -> Self {
+        let detected_type = FileType::detect(body);
+        let mut attachment = Self {
+            path: path.to_string(),
+            content_type: content_type.to_string(),
+            disposition,
+            size: body.len(),
+            detected_type,
+            hashes: Some(Hashes::of(body)),
+            mismatches: Vec::new(),
+            archive: ArchiveListing::inspect(body),
+            macros: MacroReport::inspect(body, detected_type),
+            pdf: PdfReport::inspect(body, detected_type),
+            harvest: HarvestReport::inspect(body, filename.as_deref(), detected_type),
+            recovered: false,
+            filename,
+        };
+        attachment.mismatches = attachment.find_mismatches();
+        attachment
+    }
+
+    /// Lowercased extension of the filename, without the dot.
+    pub fn extension(&self) -> Option<String> {
+        let filename = self.filename.as_deref()?.trim().trim_end_matches('.');
+        let (_, extension) = filename.rsplit_once('.')?;
+        (!extension.is_empty()).then(|| extension.to_ascii_lowercase())
+    }
+
+    fn find_mismatches(&self) -> Vec<String> {
+        let mut mismatches = Vec::new();
+        let extension = self.extension();
+        let declared = (!FileType::is_generic_mime_type(&self.content_type))
+            .then_some(self.content_type.as_str());
+
+        match self.detected_type {
+            Some(detected) => {
+                if let Some(ext) = extension.as_deref().filter(|e| !detected.has_extension(e)) {
+                    mismatches.push(format!("extension .{ext} but content is {}", detected.name));
+                }
+                if let Some(declared) = declared.filter(|d| !detected.has_mime_type(d)) {
+                    mismatches.push(format!(
+                        "declared {declared} but content is {}",
+                        detected.name
+                    ));
+                }
+            }
+            None => {
+                let expected = extension
+                    .as_deref()
+                    .and_then(|e| FileType::for_extension(e).map(|t| (e, t)));
+                if let Some((ext, expected)) = expected {
+                    if !Self::TEXT_TYPES.contains(&expected) {
+                        mismatches.push(format!(
+                            "extension .{ext} but no {} signature in the content",
+                            expected.name
+                        ));
+                    }
+                    if let Some(declared) = declared.filter(|d| !expected.has_mime_type(d)) {
+                        mismatches.push(format!("extension .{ext} but declared {declared}"));
+                    }
+                }
+            }
+        }
+        mismatches
+    }
+}
+
+impl Attachment {
+    /// Formats recognised by sniffing rather than a signature, their absence
+    /// proves nothing.
+    const TEXT_TYPES: [&'static FileType; 3] = [&FileType::HTML, &FileType::SVG, &FileType::EML];
+}
+
+impl ParsedEmail {
+    /// Builds the model from the MIME tree of the input. Reported messages
+    /// are unwrapped so the attached mail becomes the subject of the report.
+    /// Redirector links in the bodies are unwrapped with `redirects`.
+    pub fn from_tree(tree: MimePart, redirects: &RedirectTable) -> Self {
+        let warnings = tree
+            .flatten()
+            .into_iter()
+            .flat_map(|part| {
+                let name = if part.path.is_empty() {
+                    String::from("message")
+                } else {
+                    format!("part {}", part.path)
+                };
+                part.warnings.iter().map(move |w| format!("{name}: {w}"))
+            })
+            .collect();
+
+        let (mime, reported) = match ReportedMessage::detect(&tree) {
+            Some((reported, inner)) => (inner.clone(), Some(reported)),
+            None => (tree, None),
+        };
+
+        let headers = mime.headers.clone();
+        let addresses = Addresses::from_headers(&headers);
+
+        let mut bodies = Vec::new();
+        Self::collect_bodies(&mime, &mut bodies);
+
+        let link_mismatches = LinkMismatch::find(&bodies, redirects);
+
+        let parts = mime.attachments();
+        let mut attachments: Vec<Attachment> =
+            parts.iter().map(|p| Attachment::from_part(p)).collect();
+
+        let mut smuggling: Vec<Smuggling> = bodies
+            .iter()
+            .filter(|b| b.content_type == "text/html")
+            .filter_map(|b| Smuggling::find(&b.path, &b.text))
+            .collect();
+        for (part, attachment) in parts.iter().zip(&attachments) {
+            if attachment.harvest.is_some() {
+                smuggling.extend(Smuggling::find(
+                    &part.path,
+                    &String::from_utf8_lossy(&part.body),
+                ));
+            }
+        }
+        for found in &smuggling {
+            for payload in &found.payloads {
+                attachments.push(Attachment::from_smuggled(&found.path, payload));
+            }
+        }
+        let recipients: Vec<String> = addresses
+            .to
+            .iter()
+            .chain(&addresses.cc)
+            .map(Address::addr_spec)
+            .collect();
+        for harvest in attachments.iter_mut().filter_map(|a| a.harvest.as_mut()) {
+            harvest.confirm_recipients(&recipients);
+        }
+
+        // Links behind PDF buttons and in QR codes are listed with the body
+        // URLs.
+        let mut extractor = UrlExtractor::with_redirects(redirects.clone());
+        for body in &bodies {
+            extractor.add_body(body);
+        }
+        for attachment in &attachments {
+            let Some(pdf) = &attachment.pdf else {
+                continue;
+            };
+            let source = format!(
+                "URI action in part {} ({})",
+                attachment.path,
+                attachment.filename.as_deref().unwrap_or("no name")
+            );
+            for uri in &pdf.uris {
+                extractor.add(uri, &source);
+            }
+        }
+        let qr_codes = QrCode::find(&mime, &bodies);
+        for qr in &qr_codes {
+            extractor.add(&qr.text, &qr.source);
+        }
+        let urls = extractor.into_urls();
+
+        Self {
+            headers,
+            addresses,
+            mime,
+            bodies,
+            attachments,
+            urls,
+            link_mismatches,
+            smuggling,
+            qr_codes,
+            reported,
+            warnings,
+            headers_only: false,
+        }
+    }
+
+    /// Builds the model for a bare header block, such as the "Internet
+    /// headers" copied from Outlook or Gmail's "Show original".
+    pub fn from_headers(headers: HeaderMap) -> Self {
+        let mime = MimePart {
+            path: String::new(),
+            content_type: String::from("text/plain"),
+            disposition: None,
+            filename: None,
+            headers: headers.clone(),
+            body: Vec::new(),
+            text: None,
+            children: Vec::new(),
+            warnings: Vec::new(),
+        };
+
+        Self {
+            addresses: Addresses::from_headers(&headers),
+            headers,
+            mime,
+            bodies: Vec::new(),
+            attachments: Vec::new(),
+            urls: Vec::new(),
+            link_mismatches: Vec::new(),
+            smuggling: Vec::new(),
+            qr_codes: Vec::new(),
+            reported: None,
+            warnings: Vec::new(),
+            headers_only: true,
+        }
+    }
+
+    /// Number of files attached to the message, leaving out those recovered
+    /// from smuggling pages.
+    pub fn attached_count(&self) -> usize {
+        self.attachments.iter().filter(|a| !a.recovered).count()
+    }
+
+    /// Collects the text parts that make up the message body, walking the
+    /// whole tree but skipping anything that is an attachment.
+    fn collect_bodies(part: &MimePart, bodies: &mut Vec<Body>) {
+        if part.is_attachment() {
+            return;
+        }
+
+        if let Some(text) = &part.text {
+            bodies.push(Body {
+                path: part.path.to_owned(),
+                content_type: part.content_type.to_owned(),
+                text: text.to_owned(),
+            });
+        }
+        for child in &part.children {
+            Self::collect_bodies(child, bodies);
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use std::{fmt, io, path::PathBuf};
+
+/// Errors returned by the pipeline. Each variant maps to its own process exit
+/// code so callers can tell which stage failed:
+///
+/// | Code | Variant  | Meaning                                    |
+/// |------|----------|--------------------------------------------|
+/// | 0    |          | Report(s) written                          |
+/// | 2    |          | Invalid command line (reported by clap)    |
+/// | 3    | `Io`     | The input could not be read                |
+/// | 4    | `Parse`  | The input is not a message we can parse    |
+/// | 5    | `Render` | The Word document could not be built       |
+/// | 6    | `Write`  | The Word document could not be written     |
+/// | 7    | `Config` | A configuration file is missing or invalid |
+#[derive(Debug)]
+pub enum Error {
+    Io { path: PathBuf, source: io::Error },
+    Parse(String),
+    Render(String),
+    Write { path: PathBuf, source: io::Error },
+    Config { path: PathBuf, message: String },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::Io { .. } => 3,
+            Error::Parse(_) => 4,
+            Error::Render(_) => 5,
+            Error::Write { .. } => 6,
+            Error::Config { .. } => 7,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => {
+                write!(f, "Unable to read {}: {source}", path.display())
+            }
+            Error::Parse(msg) => write!(f, "Unable to parse the message: {msg}"),
+            Error::Render(msg) => write!(f, "Unable to build the word document: {msg}"),
+            Error::Write { path, source } => {
+                write!(f, "Unable to write {}: {source}", path.display())
+            }
+            Error::Config { path, message } => {
+                write!(f, "Invalid configuration in {}: {message}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } | Error::Write { source, .. } => Some(source),
+            Error::Parse(_) | Error::Render(_) | Error::Config { .. } => None,
+        }
+    }
+}
+
+impl From<mailparse::MailParseError> for Error {
+    fn from(err: mailparse::MailParseError) -> Self {
+        Error::Parse(err.to_string())
+    }
+}
@@ -0,0 +1,204 @@
+use crate::{
+    email::ParsedEmail,
+    error::{Error, Result},
+    hashes::Hashes,
+    mime::MimePart,
+};
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+use zip::{write::SimpleFileOptions, AesMode, CompressionMethod, ZipWriter};
+
+/// Writes the decoded attachments and inline parts of a message to a
+/// directory, named by their SHA-256 so nothing is opened by accident, with
+/// a `manifest.json` describing each file.
+pub struct Extractor {
+    dir: PathBuf,
+    zip: bool,
+}
+
+/// One written part, as listed in the manifest.
+#[derive(Debug, Clone)]
+pub struct ExtractedFile {
+    pub path: String,
+    pub filename: Option<String>,
+    pub content_type: String,
+    pub disposition: Option<String>,
+    pub size: usize,
+    pub hashes: Hashes,
+}
+
+impl Extractor {
+    /// With `zip` set the files are packed into one AES-256 encrypted
+    /// archive using the conventional password `infected`.
+    pub fn new(dir: PathBuf, zip: bool) -> Self {
+        Self { dir, zip }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn extract<'a>(&self, email: &'a ParsedEmail) -> Result<Vec<ExtractedFile>> {
+        fs::create_dir_all(&self.dir).map_err(|source| Self::write_error(&self.dir, source))?;
+
+        let mut files = Vec::new();
+        // Identical content is stored once but listed for every part.
+        let mut stored: Vec<(String, &[u8])> = Vec::new();
+        let mut store = |data: &'a [u8]| {
+            let hashes = Hashes::of(data);
+            if !stored.iter().any(|(name, _)| *name == hashes.sha256) {
+                stored.push((hashes.sha256.to_owned(), data));
+            }
+            hashes
+        };
+        for part in Self::parts(email) {
+            files.push(ExtractedFile {
+                path: part.path.to_owned(),
+                filename: part.filename.to_owned(),
+                content_type: part.content_type.to_owned(),
+                disposition: part.disposition.to_owned(),
+                size: part.size(),
+                hashes: store(&part.body),
+            });
+        }
+        // Files recovered from HTML smuggling pages never were MIME parts.
+        for found in &email.smuggling {
+            for payload in &found.payloads {
+                files.push(ExtractedFile {
+                    path: format!("{} smuggled", found.path),
+                    filename: payload.filename.to_owned(),
+                    content_type: payload
+                        .content_type
+                        .to_owned()
+                        .unwrap_or_else(|| String::from("application/octet-stream")),
+                    disposition: None,
+                    size: payload.data.len(),
+                    hashes: store(&payload.data),
+                });
+            }
+        }
+
+        if self.zip {
+            self.write_zip(&stored)?;
+        } else {
+            for (name, data) in &stored {
+                let path = self.dir.join(name);
+                fs::write(&path, data).map_err(|source| Self::write_error(&path, source))?;
+            }
+        }
+
+        let manifest = self.dir.join(Self::MANIFEST);
+        fs::write(&manifest, self.manifest(&files))
+            .map_err(|source| Self::write_error(&manifest, source))?;
+        Ok(files)
+    }
+
+    /// Every part with content that is not shown as the message body:
+    /// attachments, attached messages and inline images alike.
+    fn parts(email: &ParsedEmail) -> Vec<&MimePart> {
+        email
+            .mime
+            .flatten()
+            .into_iter()
+            .filter(|p| !p.body.is_empty())
+            .filter(|p| p.is_attachment() || !email.bodies.iter().any(|b| b.path == p.path))
+            .collect()
+    }
+
+    fn write_zip(&self, stored: &[(String, &[u8])]) -> Result<()> {
+        let path = self.dir.join(Self::ARCHIVE);
+        let zip_error = |err: zip::result::ZipError| {
+            Self::write_error(&path, io::Error::other(err.to_string()))
+        };
+
+        let archive = fs::File::create(&path).map_err(|source| Self::write_error(&path, source))?;
+        let mut writer = ZipWriter::new(archive);
+        let options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .with_aes_encryption(AesMode::Aes256, Self::PASSWORD);
+
+        for (name, data) in stored {
+            writer
+                .start_file(name.as_str(), options)
+                .map_err(zip_error)?;
+            writer
+                .write_all(data)
+                .map_err(|source| Self::write_error(&path, source))?;
+        }
+        writer.finish().map_err(zip_error)?;
+        Ok(())
+    }
+
+    fn manifest(&self, files: &[ExtractedFile]) -> String {
+        let optional = |value: &Option<String>| match value {
+            Some(v) => Self::json_string(v),
+            None => String::from("null"),
+        };
+
+        let entries: Vec<String> = files
+            .iter()
+            .map(|f| {
+                format!(
+                    "    {{\n      \"part\": {},\n      \"filename\": {},\n      \"content_type\": {},\n      \"disposition\": {},\n      \"size\": {},\n      \"md5\": \"{}\",\n      \"sha1\": \"{}\",\n      \"sha256\": \"{}\",\n      \"stored_as\": \"{}\"\n    }}",
+                    Self::json_string(&f.path),
+                    optional(&f.filename),
+                    Self::json_string(&f.content_type),
+                    optional(&f.disposition),
+                    f.size,
+                    f.hashes.md5,
+                    f.hashes.sha1,
+                    f.hashes.sha256,
+                    f.hashes.sha256,
+                )
+            })
+            .collect();
+
+        let archive = if self.zip {
+            format!(
+                "  \"archive\": \"{}\",\n  \"password\": \"{}\",\n",
+                Self::ARCHIVE,
+                Self::PASSWORD
+            )
+        } else {
+            String::new()
+        };
+        format!(
+            "{{\n{archive}  \"files\": [\n{}\n  ]\n}}\n",
+            entries.join(",\n")
+        )
+    }
+
+    fn json_string(value: &str) -> String {
+        let mut out = String::with_capacity(value.len() + 2);
+        out.push('"');
+        for c in value.chars() {
+            match c {
+                '"' => out.push_str("\\\""),
+                '\\' => out.push_str("\\\\"),
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                '\t' => out.push_str("\\t"),
+                c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+                c => out.push(c),
+            }
+        }
+        out.push('"');
+        out
+    }
+
+    fn write_error(path: &Path, source: io::Error) -> Error {
+        Error::Write {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+}
+
+impl Extractor {
+    const MANIFEST: &'static str = "manifest.json";
+    const ARCHIVE: &'static str = "attachments.zip";
+    const PASSWORD: &'static str = "infected";
+}
@@ -0,0 +1,482 @@
+use cfb::CompoundFile;
+use std::io::Cursor;
+use zip::ZipArchive;
+
+/// A file format recognised from its leading bytes, with the extensions and
+/// MIME types it is normally sent with.
+#[derive(Debug, PartialEq, Eq)]
+pub struct FileType {
+    pub name: &'static str,
+    pub mime_types: &'static [&'static str],
+    pub extensions: &'static [&'static str],
+}
+
+impl FileType {
+    /// Detects the format from the content alone, ignoring the name and the
+    /// declared type. `None` when no known signature matches.
+    pub fn detect(data: &[u8]) -> Option<&'static FileType> {
+        let starts = |sig: &[u8]| data.starts_with(sig);
+        let at = |offset: usize, sig: &[u8]| data.get(offset..offset + sig.len()) == Some(sig);
+
+        let detected = if starts(b"%PDF-") {
+            &Self::PDF
+        } else if starts(b"MZ") {
+            &Self::PE
+        } else if starts(b"\x7fELF") {
+            &Self::ELF
+        } else if starts(b"PK\x03\x04") || starts(b"PK\x05\x06") {
+            Self::zip_kind(data)
+        } else if starts(Self::OLE_SIGNATURE) {
+            Self::ole_kind(data)
+        } else if starts(b"Rar!\x1a\x07") {
+            &Self::RAR
+        } else if starts(b"7z\xbc\xaf\x27\x1c") {
+            &Self::SEVEN_ZIP
+        } else if starts(b"\x1f\x8b") {
+            &Self::GZIP
+        } else if at(257, b"ustar") {
+            &Self::TAR
+        } else if starts(b"MSCF") {
+            &Self::CAB
+        } else if [0x8001, 0x8801, 0x9001].iter().any(|&o| at(o, b"CD001")) {
+            &Self::ISO
+        } else if starts(b"L\x00\x00\x00\x01\x14\x02\x00") {
+            &Self::LNK
+        } else if starts(b"{\\rtf") {
+            &Self::RTF
+        } else if starts(b"\x89PNG\r\n\x1a\n") {
+            &Self::PNG
+        } else if starts(b"\xff\xd8\xff") {
+            &Self::JPEG
+        } else if starts(b"GIF87a") || starts(b"GIF89a") {
+            &Self::GIF
+        } else if starts(b"BM") && data.len() > 14 {
+            &Self::BMP
+        } else if starts(b"II*\x00") || starts(b"MM\x00*") {
+            &Self::TIFF
+        } else if starts(b"RIFF") && at(8, b"WEBP") {
+            &Self::WEBP
+        } else {
+            return Self::sniff_text(data);
+        };
+        Some(detected)
+    }
+
+    /// The known format that normally uses `extension`, if any.
+    pub fn for_extension(extension: &str) -> Option<&'static FileType> {
+        let extension = extension.to_ascii_lowercase();
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|t| t.extensions.contains(&extension.as_str()))
+    }
+
+    pub fn has_extension(&self, extension: &str) -> bool {
+        self.extensions
+            .iter()
+            .any(|e| e.eq_ignore_ascii_case(extension))
+    }
+
+    pub fn has_mime_type(&self, mime_type: &str) -> bool {
+        self.mime_types
+            .iter()
+            .any(|m| m.eq_ignore_ascii_case(mime_type))
+    }
+
+    /// Declared types that say nothing about the content and never count
+    /// as a mismatch.
+    pub fn is_generic_mime_type(mime_type: &str) -> bool {
+        Self::GENERIC_MIME_TYPES
+            .iter()
+            .any(|m| m.eq_ignore_ascii_case(mime_type))
+    }
+
+    /// Office Open XML and OpenDocument files are zip archives, the entry
+    /// names tell them apart. Office files need `[Content_Types].xml` and a
+    /// top-level `word/`, `xl/` or `ppt/` folder, a plain zip may well hold
+    /// something like `reset_password/login.html`.
+    fn zip_kind(data: &[u8]) -> &'static FileType {
+        let names: Vec<String> = match ZipArchive::new(Cursor::new(data)) {
+            Ok(zip) => zip.file_names().map(String::from).collect(),
+            Err(_) => return &Self::ZIP,
+        };
+        let has_folder = |folder: &str| names.iter().any(|n| n.starts_with(folder));
+        let office = names.iter().any(|n| n == "[Content_Types].xml");
+
+        if office && has_folder("word/") {
+            &Self::DOCX
+        } else if office && has_folder("xl/") {
+            &Self::XLSX
+        } else if office && has_folder("ppt/") {
+            &Self::PPTX
+        } else if data.get(30..38) == Some(b"mimetype")
+            && data.windows(12).any(|w| w == b"opendocument")
+        {
+            &Self::ODF
+        } else if names.iter().any(|n| n == "META-INF/MANIFEST.MF") {
+            &Self::JAR
+        } else {
+            &Self::ZIP
+        }
+    }
+
+    /// Legacy Office files, Outlook messages and installers share the OLE
+    /// container, the stream names tell them apart.
+    fn ole_kind(data: &[u8]) -> &'static FileType {
+        let cfb = match CompoundFile::open(Cursor::new(data)) {
+            Ok(cfb) => cfb,
+            Err(_) => return &Self::OLE,
+        };
+        if cfb.is_stream("/WordDocument") {
+            &Self::DOC
+        } else if cfb.is_stream("/Workbook") || cfb.is_stream("/Book") {
+            &Self::XLS
+        } else if cfb.is_stream("/PowerPoint Document") {
+            &Self::PPT
+        } else if cfb.is_stream("/__properties_version1.0") {
+            &Self::MSG
+        } else if cfb.root_entry().clsid().to_string().to_ascii_uppercase() == Self::MSI_CLSID {
+            &Self::MSI
+        } else {
+            &Self::OLE
+        }
+    }
+
+    /// Text formats have no signature, HTML, SVG and mail are recognised by
+    /// how they start.
+    fn sniff_text(data: &[u8]) -> Option<&'static FileType> {
+        let head = String::from_utf8_lossy(&data[..data.len().min(1024)]).to_ascii_lowercase();
+        let head = head.trim_start_matches('\u{feff}').trim_start();
+
+        if head.starts_with("<svg") || (head.starts_with("<?xml") && head.contains("<svg")) {
+            Some(&Self::SVG)
+        } else if [
+            "<!doctype html",
+            "<html",
+            "<head",
+            "<body",
+            "<script",
+            "<meta",
+        ]
+        .iter()
+        .any(|t| head.starts_with(t))
+            || head.contains("<html")
+        {
+            Some(&Self::HTML)
+        } else if Self::MAIL_HEADERS.iter().any(|h| head.starts_with(h)) {
+            Some(&Self::EML)
+        } else {
+            None
+        }
+    }
+}
+
+impl FileType {
+    const OLE_SIGNATURE: &'static [u8] = b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1";
+    const MSI_CLSID: &'static str = "000C1084-0000-0000-C000-000000000046";
+
+    const MAIL_HEADERS: [&'static str; 8] = [
+        "received:",
+        "return-path:",
+        "from:",
+        "mime-version:",
+        "message-id:",
+        "date:",
+        "subject:",
+        "delivered-to:",
+    ];
+
+    const GENERIC_MIME_TYPES: [&'static str; 6] = [
+        "application/octet-stream",
+        "binary/octet-stream",
+        "application/x-download",
+        "application/force-download",
+        "application/unknown",
+        "application/binary",
+    ];
+
+    pub const PDF: FileType = FileType {
+        name: "PDF document",
+        mime_types: &["application/pdf", "application/x-pdf"],
+        extensions: &["pdf"],
+    };
+    pub const PE: FileType = FileType {
+        name: "Windows executable",
+        mime_types: &[
+            "application/x-msdownload",
+            "application/x-dosexec",
+            "application/x-msdos-program",
+            "application/vnd.microsoft.portable-executable",
+            "application/x-executable",
+        ],
+        extensions: &[
+            "exe", "dll", "scr", "sys", "cpl", "com", "ocx", "drv", "efi",
+        ],
+    };
+    pub const ELF: FileType = FileType {
+        name: "ELF executable",
+        mime_types: &[
+            "application/x-executable",
+            "application/x-elf",
+            "application/x-sharedlib",
+        ],
+        extensions: &["elf", "so", "o"],
+    };
+    pub const ZIP: FileType = FileType {
+        name: "ZIP archive",
+        mime_types: &[
+            "application/zip",
+            "application/x-zip-compressed",
+            "application/x-zip",
+        ],
+        extensions: &["zip"],
+    };
+    pub const JAR: FileType = FileType {
+        name: "Java archive",
+        mime_types: &["application/java-archive", "application/x-java-archive"],
+        extensions: &["jar"],
+    };
+    pub const DOCX: FileType = FileType {
+        name: "Word document (OOXML)",
+        mime_types: &[
+            "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+            "application/vnd.ms-word.document.macroenabled.12",
+            "application/vnd.openxmlformats-officedocument.wordprocessingml.template",
+            "application/vnd.ms-word.template.macroenabled.12",
+        ],
+        extensions: &["docx", "docm", "dotx", "dotm"],
+    };
+    pub const XLSX: FileType = FileType {
+        name: "Excel workbook (OOXML)",
+        mime_types: &[
+            "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+            "application/vnd.ms-excel.sheet.macroenabled.12",
+            "application/vnd.ms-excel.sheet.binary.macroenabled.12",
+            "application/vnd.openxmlformats-officedocument.spreadsheetml.template",
+            "application/vnd.ms-excel.template.macroenabled.12",
+        ],
+        extensions: &["xlsx", "xlsm", "xlsb", "xltx", "xltm"],
+    };
+    pub const PPTX: FileType = FileType {
+        name: "PowerPoint presentation (OOXML)",
+        mime_types: &[
+            "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+            "application/vnd.ms-powerpoint.presentation.macroenabled.12",
+            "application/vnd.openxmlformats-officedocument.presentationml.slideshow",
+            "application/vnd.ms-powerpoint.slideshow.macroenabled.12",
+        ],
+        extensions: &["pptx", "pptm", "ppsx", "ppsm"],
+    };
+    pub const ODF: FileType = FileType {
+        name: "OpenDocument file",
+        mime_types: &[
+            "application/vnd.oasis.opendocument.text",
+            "application/vnd.oasis.opendocument.spreadsheet",
+            "application/vnd.oasis.opendocument.presentation",
+        ],
+        extensions: &["odt", "ods", "odp"],
+    };
+    pub const OLE: FileType = FileType {
+        name: "OLE compound file",
+        mime_types: &["application/x-ole-storage", "application/cdfv2"],
+        extensions: &[
+            "doc", "xls", "ppt", "msg", "msi", "pub", "vsd", "dot", "xlt", "pps",
+        ],
+    };
+    pub const DOC: FileType = FileType {
+        name: "Word document (OLE)",
+        mime_types: &["application/msword"],
+        extensions: &["doc", "dot"],
+    };
+    pub const XLS: FileType = FileType {
+        name: "Excel workbook (OLE)",
+        mime_types: &["application/vnd.ms-excel", "application/msexcel"],
+        extensions: &["xls", "xlt", "xla"],
+    };
+    pub const PPT: FileType = FileType {
+        name: "PowerPoint presentation (OLE)",
+        mime_types: &["application/vnd.ms-powerpoint", "application/mspowerpoint"],
+        extensions: &["ppt", "pps", "pot"],
+    };
+    pub const MSG: FileType = FileType {
+        name: "Outlook message",
+        mime_types: &["application/vnd.ms-outlook", "application/x-msg"],
+        extensions: &["msg"],
+    };
+    pub const MSI: FileType = FileType {
+        name: "Windows installer",
+        mime_types: &[
+            "application/x-msi",
+            "application/x-ole-storage",
+            "application/x-msdownload",
+        ],
+        extensions: &["msi", "msp"],
+    };
+    pub const RAR: FileType = FileType {
+        name: "RAR archive",
+        mime_types: &[
+            "application/vnd.rar",
+            "application/x-rar-compressed",
+            "application/x-rar",
+        ],
+        extensions: &["rar"],
+    };
+    pub const SEVEN_ZIP: FileType = FileType {
+        name: "7-Zip archive",
+        mime_types: &["application/x-7z-compressed"],
+        extensions: &["7z"],
+    };
+    pub const GZIP: FileType = FileType {
+        name: "gzip archive",
+        mime_types: &["application/gzip", "application/x-gzip"],
+        extensions: &["gz", "tgz"],
+    };
+    pub const TAR: FileType = FileType {
+        name: "tar archive",
+        mime_types: &["application/x-tar"],
+        extensions: &["tar"],
+    };
+    pub const CAB: FileType = FileType {
+        name: "Cabinet archive",
+        mime_types: &["application/vnd.ms-cab-compressed"],
+        extensions: &["cab"],
+    };
+    pub const ISO: FileType = FileType {
+        name: "ISO disk image",
+        mime_types: &["application/x-iso9660-image", "application/x-cd-image"],
+        extensions: &["iso", "img"],
+    };
+    pub const LNK: FileType = FileType {
+        name: "Windows shortcut",
+        mime_types: &["application/x-ms-shortcut"],
+        extensions: &["lnk"],
+    };
+    pub const RTF: FileType = FileType {
+        name: "RTF document",
+        mime_types: &["application/rtf", "text/rtf"],
+        extensions: &["rtf", "doc"],
+    };
+    pub const PNG: FileType = FileType {
+        name: "PNG image",
+        mime_types: &["image/png"],
+        extensions: &["png"],
+    };
+    pub const JPEG: FileType = FileType {
+        name: "JPEG image",
+        mime_types: &["image/jpeg", "image/jpg", "image/pjpeg"],
+        extensions: &["jpg", "jpeg", "jpe", "jfif"],
+    };
+    pub const GIF: FileType = FileType {
+        name: "GIF image",
+        mime_types: &["image/gif"],
+        extensions: &["gif"],
+    };
+    pub const BMP: FileType = FileType {
+        name: "BMP image",
+        mime_types: &["image/bmp", "image/x-bmp", "image/x-ms-bmp"],
+        extensions: &["bmp", "dib"],
+    };
+    pub const TIFF: FileType = FileType {
+        name: "TIFF image",
+        mime_types: &["image/tiff"],
+        extensions: &["tif", "tiff"],
+    };
+    pub const WEBP: FileType = FileType {
+        name: "WebP image",
+        mime_types: &["image/webp"],
+        extensions: &["webp"],
+    };
+    pub const SVG: FileType = FileType {
+        name: "SVG image",
+        mime_types: &["image/svg+xml"],
+        extensions: &["svg", "svgz"],
+    };
+    pub const HTML: FileType = FileType {
+        name: "HTML document",
+        mime_types: &["text/html", "application/xhtml+xml"],
+        extensions: &["html", "htm", "shtml", "xhtml", "mht", "mhtml"],
+    };
+    pub const EML: FileType = FileType {
+        name: "Email message",
+        mime_types: &["message/rfc822", "message/global"],
+        extensions: &["eml", "mht"],
+    };
+
+    /// The Office Open XML formats, which can carry macros and images.
+    pub(crate) const OOXML: [&'static FileType; 3] = [&Self::DOCX, &Self::XLSX, &Self::PPTX];
+
+    const ALL: [&'static FileType; 32] = [
+        &Self::PDF,
+        &Self::PE,
+        &Self::ELF,
+        &Self::ZIP,
+        &Self::JAR,
+        &Self::DOCX,
+        &Self::XLSX,
+        &Self::PPTX,
+        &Self::ODF,
+        &Self::DOC,
+        &Self::XLS,
+        &Self::PPT,
+        &Self::MSG,
+        &Self::MSI,
+        &Self::OLE,
+        &Self::RAR,
+        &Self::SEVEN_ZIP,
+        &Self::GZIP,
+        &Self::TAR,
+        &Self::CAB,
+        &Self::ISO,
+        &Self::LNK,
+        &Self::RTF,
+        &Self::PNG,
+        &Self::JPEG,
+        &Self::GIF,
+        &Self::BMP,
+        &Self::TIFF,
+        &Self::WEBP,
+        &Self::SVG,
+        &Self::HTML,
+        &Self::EML,
+    ];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    fn zip(names: &[&str]) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for name in names {
+            writer
+                .start_file(*name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(b"x").unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn office_zips() {
+        let docx = zip(&["[Content_Types].xml", "_rels/.rels", "word/document.xml"]);
+        assert_eq!(FileType::detect(&docx), Some(&FileType::DOCX));
+        let xlsx = zip(&["[Content_Types].xml", "xl/workbook.xml"]);
+        assert_eq!(FileType::detect(&xlsx), Some(&FileType::XLSX));
+        let jar = zip(&["META-INF/MANIFEST.MF", "a/Main.class"]);
+        assert_eq!(FileType::detect(&jar), Some(&FileType::JAR));
+    }
+
+    #[test]
+    fn plain_zips_with_office_like_names() {
+        for names in [
+            &["reset_password/login.html", "reset_password/style.css"][..],
+            &["backup/xl/report.xlsx", "keyword/notes.txt"],
+            // A folder alone is not enough without the content types part.
+            &["word/document.xml"],
+        ] {
+            assert_eq!(FileType::detect(&zip(names)), Some(&FileType::ZIP));
+        }
+    }
+}
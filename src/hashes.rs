@@ -0,0 +1,25 @@
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+/// Hex digests of a file, the set analysts paste into reputation services.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hashes {
+    pub md5: String,
+    pub sha1: String,
+    pub sha256: String,
+}
+
+impl Hashes {
+    pub fn of(data: &[u8]) -> Self {
+        Self {
+            md5: Self::hex(&Md5::digest(data)),
+            sha1: Self::hex(&Sha1::digest(data)),
+            sha256: Self::hex(&Sha256::digest(data)),
+        }
+    }
+
+    fn hex(digest: &[u8]) -> String {
+        digest.iter().map(|b| format!("{b:02x}")).collect()
+    }
+}
@@ -0,0 +1,77 @@
+use crate::decode::Decode;
+
+/// Ordered list of header fields. Every occurrence of a field is kept in the
+/// order it appeared in the message, and lookups ignore the case of the key so
+/// `Received-Spf` and `Received-SPF` refer to the same field.
+#[derive(Debug, Clone, Default)]
+pub struct HeaderMap {
+    entries: Vec<HeaderField>,
+}
+
+#[derive(Debug, Clone)]
+struct HeaderField {
+    key: String,
+    value: String,
+    /// Unfolded value before encoded-words were decoded. Structured fields
+    /// such as address lists must be split before decoding.
+    raw: String,
+}
+
+impl HeaderMap {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, key: String, value: String) {
+        let raw = value.to_owned();
+        self.push_raw(key, value, raw);
+    }
+
+    pub fn push_raw(&mut self, key: String, value: String, raw: String) {
+        self.entries.push(HeaderField { key, value, raw });
+    }
+
+    /// First occurrence of `key`, which for trace fields is the one added by
+    /// the last hop.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.find(key).map(|f| f.value.as_str())
+    }
+
+    /// Like [`HeaderMap::get`] but without encoded-words decoded.
+    pub fn get_raw(&self, key: &str) -> Option<&str> {
+        self.find(key).map(|f| f.raw.as_str())
+    }
+
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        self.entries
+            .iter()
+            .filter(|f| f.key.eq_ignore_ascii_case(key))
+            .map(|f| f.value.as_str())
+            .collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .iter()
+            .map(|f| (f.key.as_str(), f.value.as_str()))
+    }
+
+    fn find(&self, key: &str) -> Option<&HeaderField> {
+        self.entries
+            .iter()
+            .find(|f| f.key.eq_ignore_ascii_case(key))
+    }
+}
+
+impl<'a> From<&'a [mailparse::MailHeader<'a>]> for HeaderMap {
+    fn from(headers: &'a [mailparse::MailHeader<'a>]) -> Self {
+        let mut map = Self::new();
+        for h in headers {
+            let raw = Decode::header_raw(h);
+            map.push_raw(h.get_key(), Decode::encoded_words(&raw), raw);
+        }
+        map
+    }
+}
@@ -0,0 +1,65 @@
+//! Parses suspicious emails (.eml, .msg, mbox, Maildir) into a typed
+//! [`ParsedEmail`] and renders the initial analysis report as a Word document.
+//!
+//! ```no_run
+//! use eml_2_docx::{Mail, NewDocx};
+//! use std::path::PathBuf;
+//!
+//! # fn main() -> eml_2_docx::Result<()> {
+//! let mail = Mail::new(PathBuf::from("sample.eml"));
+//! let email = mail.parse(&mail.get_content()?)?;
+//!
+//! let report = NewDocx::new(PathBuf::from("sample.docx"), String::from("1234"));
+//! report.create_docx(report.generate_content(&email))?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod address;
+pub mod archive;
+pub mod decode;
+pub mod defang;
+pub mod email;
+pub mod error;
+pub mod extract;
+pub mod filetype;
+pub mod gateway;
+pub mod harvest;
+pub mod hashes;
+pub mod headers;
+pub mod links;
+pub mod macros;
+pub mod mail;
+pub mod mailbox;
+pub mod mime;
+pub mod msg;
+pub mod newdoc;
+pub mod pdf;
+pub mod qr;
+pub mod redirect;
+pub mod reported;
+pub mod smuggling;
+pub mod urls;
+
+pub use address::Address;
+pub use archive::{ArchiveEntry, ArchiveListing};
+pub use defang::Defang;
+pub use email::{Addresses, Attachment, Body, ParsedEmail};
+pub use error::{Error, Result};
+pub use extract::{ExtractedFile, Extractor};
+pub use filetype::FileType;
+pub use gateway::Gateway;
+pub use harvest::HarvestReport;
+pub use hashes::Hashes;
+pub use headers::HeaderMap;
+pub use links::LinkMismatch;
+pub use macros::{MacroReport, VbaModule};
+pub use mail::Mail;
+pub use mime::MimePart;
+pub use newdoc::NewDocx;
+pub use pdf::PdfReport;
+pub use qr::QrCode;
+pub use redirect::{ParamEncoding, RedirectTable, Redirector};
+pub use reported::ReportedMessage;
+pub use smuggling::{SmuggledPayload, Smuggling};
+pub use urls::{ExtractedUrl, UnwrappedUrl, UrlExtractor};
@@ -0,0 +1,191 @@
+use crate::{
+    defang::Defang,
+    email::Body,
+    redirect::RedirectTable,
+    urls::{UrlExtractor, TAG},
+};
+use regex::Regex;
+use std::sync::LazyLock;
+use url::Url;
+
+/// A link whose visible text names a different site than the one it opens,
+/// the classic `<a href="https://evil.example">https://bank.com</a>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkMismatch {
+    pub path: String,
+    pub text: String,
+    pub href: String,
+    /// Where the link leads once gateway rewrites and redirectors are
+    /// removed, the same as `href` for a plain link.
+    pub destination: String,
+    pub text_host: String,
+    /// Host of `destination`.
+    pub href_host: String,
+}
+
+static ANCHOR: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<a\b([^>]*)>(.*?)</a\s*>").unwrap());
+
+static HREF: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?is)\bhref\s*=\s*("[^"]*"|'[^']*'|[^\s>]+)"#).unwrap());
+
+// Bare domain text such as `bank.com` or `www.bank.com/login`.
+static DOMAIN_TEXT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)^(?:[a-z0-9](?:[a-z0-9-]*[a-z0-9])?\.)+[a-z]{2,63}(?::\d+)?(?:[/?#]\S*)?$")
+        .unwrap()
+});
+
+impl LinkMismatch {
+    /// Compares the visible text of every HTML link with its destination,
+    /// unwrapping Safe Links, URL Defense and the redirectors in `redirects`
+    /// first so that a rewritten link to the site it names is not flagged.
+    pub fn find(bodies: &[Body], redirects: &RedirectTable) -> Vec<LinkMismatch> {
+        let extractor = UrlExtractor::with_redirects(redirects.clone());
+        let mut mismatches = Vec::new();
+        for body in bodies.iter().filter(|b| b.content_type == "text/html") {
+            for cap in ANCHOR.captures_iter(&body.text) {
+                let href = match HREF.captures(&cap[1]) {
+                    Some(h) => {
+                        UrlExtractor::html_unescape(h[1].trim_matches(|c| c == '"' || c == '\''))
+                    }
+                    None => continue,
+                };
+                let text = UrlExtractor::html_unescape(&TAG.replace_all(&cap[2], ""));
+                let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+                let text_host = match Self::host_of_text(&text) {
+                    Some(host) => host,
+                    None => continue,
+                };
+                let href = href.trim().to_string();
+                let destination = match extractor.unwrap(&href).pop() {
+                    Some(unwrapped) => unwrapped.url,
+                    None => href.to_owned(),
+                };
+                // Mimecast links only give the destination domain.
+                let href_host = match Self::host_of_text(&destination) {
+                    Some(host) => host,
+                    None => continue,
+                };
+
+                if !Self::same_site(&text_host, &href_host) {
+                    let mismatch = LinkMismatch {
+                        path: body.path.to_owned(),
+                        text,
+                        href,
+                        destination,
+                        text_host,
+                        href_host,
+                    };
+                    if !mismatches.contains(&mismatch) {
+                        mismatches.push(mismatch);
+                    }
+                }
+            }
+        }
+        mismatches
+    }
+
+    /// Host named by link text that looks like a URL or a domain, `None` for
+    /// ordinary text such as "Click here". Text without a scheme or `www.`
+    /// must end in a known top-level domain that is not also a common file
+    /// extension, so filenames such as `Invoice.pdf` or `README.md` are not
+    /// taken for hosts.
+    fn host_of_text(text: &str) -> Option<String> {
+        let text = text.trim().trim_end_matches(['.', ',', ';', ':']);
+        if text.contains(char::is_whitespace) {
+            return None;
+        }
+
+        let explicit = text.contains("://");
+        let candidate = if explicit {
+            text.to_string()
+        } else if DOMAIN_TEXT.is_match(text) {
+            format!("http://{text}")
+        } else {
+            return None;
+        };
+        let host = Url::parse(&candidate)
+            .ok()
+            .and_then(|u| u.host_str().map(|h| h.to_ascii_lowercase()))?;
+        let known = explicit || host.starts_with("www.") || Self::is_domain_name(&host);
+        known.then_some(host)
+    }
+
+    fn is_domain_name(host: &str) -> bool {
+        let suffix = host.rsplit('.').next().unwrap_or_default();
+        Defang::has_known_tld(host) && !Self::FILE_EXTENSIONS.contains(&suffix)
+    }
+
+    /// Hosts match when equal or when one is a subdomain of the other, so
+    /// `bank.com` shown for `www.bank.com` or `login.bank.com` is fine.
+    fn same_site(text_host: &str, href_host: &str) -> bool {
+        let text_host = text_host.trim_start_matches("www.");
+        let href_host = href_host.trim_start_matches("www.");
+        text_host == href_host
+            || href_host.ends_with(&format!(".{text_host}"))
+            || text_host.ends_with(&format!(".{href_host}"))
+    }
+}
+
+impl LinkMismatch {
+    /// Top-level domains that are more often the extension of a linked file.
+    const FILE_EXTENSIONS: [&'static str; 7] = ["md", "sh", "py", "pl", "rs", "ps", "cs"];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(html: &str) -> Vec<LinkMismatch> {
+        let body = Body {
+            path: String::from("1"),
+            content_type: String::from("text/html"),
+            text: html.to_string(),
+        };
+        LinkMismatch::find(&[body], &RedirectTable::default())
+    }
+
+    #[test]
+    fn plain_mismatch() {
+        let found = find(r#"<a href="https://evil.com/x">https://paypal.com</a>"#);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].text_host, "paypal.com");
+        assert_eq!(found[0].href_host, "evil.com");
+        assert!(find(r#"<a href="https://login.bank.com/">bank.com</a>"#).is_empty());
+    }
+
+    #[test]
+    fn filenames_are_not_hosts() {
+        assert!(find(
+            r#"<a href="https://contoso.sharepoint.com/sites/f/Invoice.pdf">Invoice.pdf</a>"#
+        )
+        .is_empty());
+        assert!(
+            find(r#"<a href="https://github.com/o/r/blob/main/README.md">README.md</a>"#)
+                .is_empty()
+        );
+        // Unknown TLDs still count with a scheme or `www.`.
+        assert_eq!(
+            find(r#"<a href="https://evil.com/">https://bank.example</a>"#).len(),
+            1
+        );
+        assert_eq!(
+            find(r#"<a href="https://evil.com/">www.bank.example</a>"#).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn rewritten_links_compare_the_destination() {
+        let safe = r#"<a href="https://nam02.safelinks.protection.outlook.com/?url=https%3A%2F%2Fbank.com%2F&amp;data=05">https://bank.com</a>"#;
+        assert!(find(safe).is_empty());
+
+        let wrapped =
+            r#"<a href="https://www.google.com/url?q=https%3A%2F%2Fevil.com%2Fx">bank.com</a>"#;
+        let found = find(wrapped);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].destination, "https://evil.com/x");
+        assert_eq!(found[0].href_host, "evil.com");
+    }
+}
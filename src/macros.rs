@@ -0,0 +1,572 @@
+use crate::{decode::Decode, filetype::FileType};
+use cfb::CompoundFile;
+use regex::Regex;
+use std::{
+    io::{Cursor, Read},
+    path::Path,
+    sync::LazyLock,
+};
+use zip::ZipArchive;
+
+/// One VBA module with its decompressed source code.
+#[derive(Debug, Clone)]
+pub struct VbaModule {
+    pub name: String,
+    pub source: String,
+}
+
+/// VBA projects and Excel 4.0 (XLM) macro sheets found in a legacy (.doc,
+/// .xls) or OOXML (.docm, .xlsm) Office attachment, or in a bare
+/// `vbaProject.bin`.
+#[derive(Debug, Clone)]
+pub struct MacroReport {
+    pub modules: Vec<VbaModule>,
+    /// Procedures Office runs by itself, such as `AutoOpen` or
+    /// `Workbook_Open`.
+    pub auto_exec: Vec<String>,
+    /// Calls and objects used to run commands or fetch payloads, such as
+    /// `Shell` or `URLDownloadToFile`.
+    pub suspicious: Vec<String>,
+    /// Macro sheet names, with their visibility when it is known.
+    pub xlm_sheets: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+static AUTO_EXEC: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r"(?i)\b(?:{})\b",
+        MacroReport::AUTO_EXEC_NAMES.join("|")
+    ))
+    .unwrap()
+});
+
+static SUSPICIOUS: LazyLock<Regex> = LazyLock::new(|| {
+    let names: Vec<String> = MacroReport::SUSPICIOUS_NAMES
+        .iter()
+        .map(|n| regex::escape(n))
+        .collect();
+    Regex::new(&format!(r"(?i)\b(?:{})\b", names.join("|"))).unwrap()
+});
+
+impl MacroReport {
+    /// Looks for macros in an Office attachment, `None` when it has none or
+    /// is not an Office file.
+    pub fn inspect(data: &[u8], detected: Option<&FileType>) -> Option<MacroReport> {
+        let detected = detected?;
+        let mut report = MacroReport {
+            modules: Vec::new(),
+            auto_exec: Vec::new(),
+            suspicious: Vec::new(),
+            xlm_sheets: Vec::new(),
+            warnings: Vec::new(),
+        };
+
+        if Self::OLE_TYPES.contains(&detected) {
+            report.read_ole(data, "attachment");
+        } else if FileType::OOXML.contains(&detected) {
+            report.read_ooxml(data);
+        } else {
+            return None;
+        }
+
+        for module in &report.modules {
+            Self::collect(&AUTO_EXEC, &module.source, &mut report.auto_exec);
+            Self::collect(&SUSPICIOUS, &module.source, &mut report.suspicious);
+        }
+
+        let found = !report.modules.is_empty() || !report.xlm_sheets.is_empty();
+        (found || !report.warnings.is_empty()).then_some(report)
+    }
+
+    /// Whether anything was found that can run code when the file is opened.
+    pub fn has_findings(&self) -> bool {
+        !self.auto_exec.is_empty() || !self.suspicious.is_empty() || !self.xlm_sheets.is_empty()
+    }
+
+    /// Short description of the findings for the report summary.
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if !self.modules.is_empty() {
+            parts.push(format!("{} VBA module(s)", self.modules.len()));
+        }
+        if !self.auto_exec.is_empty() {
+            parts.push(format!("auto-exec {}", self.auto_exec.join(", ")));
+        }
+        if !self.suspicious.is_empty() {
+            parts.push(format!("suspicious calls {}", self.suspicious.join(", ")));
+        }
+        if !self.xlm_sheets.is_empty() {
+            parts.push(format!("XLM macro sheet(s) {}", self.xlm_sheets.join(", ")));
+        }
+        parts.join("; ")
+    }
+
+    fn read_ooxml(&mut self, data: &[u8]) {
+        let mut zip = match ZipArchive::new(Cursor::new(data)) {
+            Ok(zip) => zip,
+            Err(err) => {
+                self.warnings
+                    .push(format!("attachment: zip not readable, {err}"));
+                return;
+            }
+        };
+
+        let names: Vec<String> = zip.file_names().map(str::to_string).collect();
+        for name in names {
+            let lower = name.to_ascii_lowercase();
+            if lower.starts_with("xl/macrosheets/") && lower.ends_with(".xml") {
+                self.xlm_sheets.push(name);
+            } else if lower.ends_with("vbaproject.bin") {
+                let mut content = Vec::new();
+                let read = zip.by_name(&name).map_err(|e| e.to_string()).and_then(|f| {
+                    f.take(Self::MAX_PROJECT_SIZE)
+                        .read_to_end(&mut content)
+                        .map_err(|e| e.to_string())
+                });
+                match read {
+                    Ok(_) => self.read_ole(&content, &name),
+                    Err(err) => self.warnings.push(format!("{name}: {err}")),
+                }
+            }
+        }
+    }
+
+    fn read_ole(&mut self, data: &[u8], label: &str) {
+        let mut cfb = match CompoundFile::open(Cursor::new(data)) {
+            Ok(cfb) => cfb,
+            Err(err) => {
+                self.warnings
+                    .push(format!("{label}: OLE not readable, {err}"));
+                return;
+            }
+        };
+
+        // Word keeps the project under Macros, Excel under _VBA_PROJECT_CUR
+        // and a bare vbaProject.bin at the root, the VBA storage with its dir
+        // stream is the same everywhere.
+        let projects: Vec<_> = cfb
+            .walk()
+            .filter(|e| e.is_stream() && e.name().eq_ignore_ascii_case("dir"))
+            .filter_map(|e| e.path().parent().map(Path::to_path_buf))
+            .filter(|p| p.file_name().is_some_and(|n| n.eq_ignore_ascii_case("VBA")))
+            .collect();
+        for storage in projects {
+            if let Err(err) = self.read_project(&mut cfb, &storage) {
+                self.warnings
+                    .push(format!("{label}: VBA project not readable, {err}"));
+            }
+        }
+
+        for book in ["/Workbook", "/Book"] {
+            if cfb.is_stream(book) {
+                match Self::read_stream(&mut cfb, Path::new(book)) {
+                    Ok(stream) => self.xlm_sheets.extend(Self::macro_sheets(&stream)),
+                    Err(err) => self.warnings.push(format!("{label}{book}: {err}")),
+                }
+            }
+        }
+    }
+
+    /// Reads the modules listed in the `dir` stream of a VBA storage.
+    fn read_project(
+        &mut self,
+        cfb: &mut CompoundFile<Cursor<&[u8]>>,
+        storage: &Path,
+    ) -> Result<(), String> {
+        let dir = Self::decompress(&Self::read_stream(cfb, &storage.join("dir"))?)?;
+
+        let mut codepage = None;
+        let mut modules: Vec<(String, Option<String>, usize)> = Vec::new();
+        let mut pos = 0;
+        while pos + 6 <= dir.len() {
+            let id = u16::from_le_bytes([dir[pos], dir[pos + 1]]);
+            let mut size = u32::from_le_bytes(dir[pos + 2..pos + 6].try_into().unwrap()) as usize;
+            // PROJECTVERSION declares 4 bytes but carries 6.
+            if id == 0x0009 {
+                size = 6;
+            }
+            let start = pos + 6;
+            let Some(value) = dir.get(start..start + size) else {
+                break;
+            };
+            match id {
+                0x0003 if size >= 2 => codepage = Some(u16::from_le_bytes([value[0], value[1]])),
+                0x0019 => modules.push((Self::text(value, codepage), None, 0)),
+                0x001A => {
+                    if let Some(module) = modules.last_mut() {
+                        module.1 = Some(Self::text(value, codepage));
+                    }
+                }
+                0x0031 if size >= 4 => {
+                    if let Some(module) = modules.last_mut() {
+                        module.2 = u32::from_le_bytes(value[..4].try_into().unwrap()) as usize;
+                    }
+                }
+                _ => {}
+            }
+            pos = start + size;
+        }
+
+        for (name, stream, offset) in modules {
+            let stream = stream.unwrap_or_else(|| name.to_owned());
+            let source = Self::read_stream(cfb, &storage.join(&stream)).and_then(|data| {
+                let compressed = data
+                    .get(offset..)
+                    .ok_or_else(|| String::from("source offset outside the stream"))?;
+                Self::decompress(compressed)
+            });
+            match source {
+                Ok(source) => self.modules.push(VbaModule {
+                    name,
+                    source: Self::text(&source, codepage),
+                }),
+                Err(err) => self.warnings.push(format!("module {name}: {err}")),
+            }
+        }
+        Ok(())
+    }
+
+    fn read_stream(cfb: &mut CompoundFile<Cursor<&[u8]>>, path: &Path) -> Result<Vec<u8>, String> {
+        let stream = cfb.open_stream(path).map_err(|e| e.to_string())?;
+        let mut data = Vec::new();
+        stream
+            .take(Self::MAX_PROJECT_SIZE)
+            .read_to_end(&mut data)
+            .map_err(|e| e.to_string())?;
+        Ok(data)
+    }
+
+    /// Decompresses a CompressedContainer as described in MS-OVBA 2.4.1.
+    fn decompress(data: &[u8]) -> Result<Vec<u8>, String> {
+        if data.first() != Some(&1) {
+            return Err(String::from("not a compressed container"));
+        }
+        let mut out = Vec::new();
+        let mut pos = 1;
+        while pos + 2 <= data.len() {
+            let header = u16::from_le_bytes([data[pos], data[pos + 1]]);
+            let chunk_end = (pos + 3 + usize::from(header & 0x0FFF)).min(data.len());
+            pos += 2;
+
+            if header & 0x8000 == 0 {
+                // Raw chunk, always 4096 literal bytes.
+                let end = (pos + 4096).min(data.len());
+                out.extend_from_slice(&data[pos..end]);
+                pos = end;
+                continue;
+            }
+
+            let chunk_start = out.len();
+            while pos < chunk_end {
+                let flags = data[pos];
+                pos += 1;
+                for bit in 0..8 {
+                    if pos >= chunk_end {
+                        break;
+                    }
+                    if flags & (1 << bit) == 0 {
+                        out.push(data[pos]);
+                        pos += 1;
+                        continue;
+                    }
+                    if pos + 2 > chunk_end {
+                        return Err(String::from("truncated copy token"));
+                    }
+                    let token = u16::from_le_bytes([data[pos], data[pos + 1]]);
+                    pos += 2;
+
+                    let difference = out.len() - chunk_start;
+                    let mut bit_count = 4;
+                    while (1 << bit_count) < difference {
+                        bit_count += 1;
+                    }
+                    let length_mask = 0xFFFFu16 >> bit_count;
+                    let length = usize::from(token & length_mask) + 3;
+                    let offset = usize::from(token >> (16 - bit_count)) + 1;
+                    if offset > difference {
+                        return Err(String::from("copy token points before the chunk"));
+                    }
+                    let from = out.len() - offset;
+                    for i in 0..length {
+                        out.push(out[from + i]);
+                    }
+                }
+            }
+            if out.len() as u64 > Self::MAX_PROJECT_SIZE {
+                return Err(String::from("decompressed source is too large"));
+            }
+        }
+        Ok(out)
+    }
+
+    /// Names of the macro sheets declared by BoundSheet8 records in the
+    /// globals substream of a BIFF8 workbook.
+    fn macro_sheets(stream: &[u8]) -> Vec<String> {
+        let mut sheets = Vec::new();
+        let mut pos = 0;
+        while pos + 4 <= stream.len() {
+            let id = u16::from_le_bytes([stream[pos], stream[pos + 1]]);
+            let size = usize::from(u16::from_le_bytes([stream[pos + 2], stream[pos + 3]]));
+            let Some(record) = stream.get(pos + 4..pos + 4 + size) else {
+                break;
+            };
+            // EOF ends the globals, the sheet substreams follow.
+            if id == 0x000A {
+                break;
+            }
+            if id == 0x0085 && record.len() >= 8 && record[5] == 0x01 {
+                let state = match record[4] & 0x03 {
+                    1 => " (hidden)",
+                    2 => " (very hidden)",
+                    _ => "",
+                };
+                sheets.push(format!("{}{state}", Self::sheet_name(&record[6..])));
+            }
+            pos += 4 + size;
+        }
+        sheets
+    }
+
+    /// ShortXLUnicodeString: a character count, a flag for 16-bit
+    /// characters, then the characters.
+    fn sheet_name(data: &[u8]) -> String {
+        let count = usize::from(data[0]);
+        let chars = &data[2.min(data.len())..];
+        if data.get(1).is_some_and(|f| f & 1 == 1) {
+            let units: Vec<u16> = chars
+                .chunks_exact(2)
+                .take(count)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        } else {
+            chars[..count.min(chars.len())]
+                .iter()
+                .map(|&b| char::from(b))
+                .collect()
+        }
+    }
+
+    /// Decodes project text with the code page from the `dir` stream.
+    fn text(bytes: &[u8], codepage: Option<u16>) -> String {
+        let label = codepage.map(|cp| match cp {
+            65001 => String::from("utf-8"),
+            932 => String::from("shift_jis"),
+            936 => String::from("gbk"),
+            949 => String::from("euc-kr"),
+            950 => String::from("big5"),
+            10000 => String::from("macintosh"),
+            cp => format!("windows-{cp}"),
+        });
+        Decode::charset_decode(label.as_deref(), bytes)
+    }
+
+    /// Adds each distinct match to `found`, spelled as in the lists below.
+    fn collect(pattern: &Regex, source: &str, found: &mut Vec<String>) {
+        for m in pattern.find_iter(source) {
+            let name = Self::AUTO_EXEC_NAMES
+                .iter()
+                .chain(Self::SUSPICIOUS_NAMES.iter())
+                .find(|n| n.eq_ignore_ascii_case(m.as_str()))
+                .map_or_else(|| m.as_str().to_string(), |n| n.to_string());
+            if !found.contains(&name) {
+                found.push(name);
+            }
+        }
+    }
+}
+
+impl MacroReport {
+    const MAX_PROJECT_SIZE: u64 = 50 * 1024 * 1024;
+
+    const OLE_TYPES: [&'static FileType; 4] = [
+        &FileType::OLE,
+        &FileType::DOC,
+        &FileType::XLS,
+        &FileType::PPT,
+    ];
+
+    const AUTO_EXEC_NAMES: [&'static str; 18] = [
+        "AutoOpen",
+        "AutoExec",
+        "AutoClose",
+        "AutoNew",
+        "AutoExit",
+        "Auto_Open",
+        "Auto_Close",
+        "Document_Open",
+        "Document_Close",
+        "Document_New",
+        "DocumentOpen",
+        "Document_ContentControlOnEnter",
+        "Workbook_Open",
+        "Workbook_Activate",
+        "Workbook_BeforeClose",
+        "Workbook_Close",
+        "Worksheet_Activate",
+        "Presentation_Open",
+    ];
+
+    const SUSPICIOUS_NAMES: [&'static str; 26] = [
+        "Shell",
+        "ShellExecute",
+        "WScript.Shell",
+        "Shell.Application",
+        "CreateObject",
+        "GetObject",
+        "URLDownloadToFile",
+        "MSXML2.XMLHTTP",
+        "Microsoft.XMLHTTP",
+        "WinHttp.WinHttpRequest",
+        "ADODB.Stream",
+        "SaveToFile",
+        "CallByName",
+        "ExecuteExcel4Macro",
+        "MacScript",
+        "Environ",
+        "Kill",
+        "PowerShell",
+        "cmd.exe",
+        "mshta",
+        "certutil",
+        "regsvr32",
+        "rundll32",
+        "VirtualAlloc",
+        "RtlMoveMemory",
+        "CreateThread",
+    ];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    /// A compressed container holding `data` as literal tokens only.
+    fn container(data: &[u8]) -> Vec<u8> {
+        let mut chunk = Vec::new();
+        for group in data.chunks(8) {
+            chunk.push(0);
+            chunk.extend_from_slice(group);
+        }
+        let header = 0xB000 | (chunk.len() as u16 + 2 - 3);
+        let mut out = vec![1];
+        out.extend_from_slice(&header.to_le_bytes());
+        out.extend(chunk);
+        out
+    }
+
+    fn record(id: u16, value: &[u8]) -> Vec<u8> {
+        let mut out = id.to_le_bytes().to_vec();
+        out.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        out.extend_from_slice(value);
+        out
+    }
+
+    #[test]
+    fn decompress_spec_examples() {
+        // [MS-OVBA] 3.2.1, no compression possible.
+        let literal = [
+            0x01, 0x19, 0xB0, 0x00, 0x61, 0x62, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x00, 0x69,
+            0x6A, 0x6B, 0x6C, 0x6D, 0x6E, 0x6F, 0x70, 0x00, 0x71, 0x72, 0x73, 0x74, 0x75, 0x76,
+            0x2E,
+        ];
+        assert_eq!(
+            MacroReport::decompress(&literal).unwrap(),
+            b"abcdefghijklmnopqrstuv."
+        );
+
+        // 3.2.2, normal compression.
+        let normal = [
+            0x01, 0x2F, 0xB0, 0x00, 0x23, 0x61, 0x61, 0x61, 0x62, 0x63, 0x64, 0x65, 0x82, 0x66,
+            0x00, 0x70, 0x61, 0x67, 0x68, 0x69, 0x6A, 0x01, 0x38, 0x08, 0x61, 0x6B, 0x6C, 0x00,
+            0x30, 0x6D, 0x6E, 0x6F, 0x70, 0x06, 0x71, 0x02, 0x70, 0x04, 0x10, 0x72, 0x73, 0x74,
+            0x75, 0x76, 0x10, 0x77, 0x78, 0x79, 0x7A, 0x00, 0x3C,
+        ];
+        assert_eq!(
+            MacroReport::decompress(&normal).unwrap(),
+            b"#aaabcdefaaaaghijaaaaaklaaamnopqaaaaaaaaaaaarstuvwxyzaaa"
+        );
+
+        // One literal then a copy token that overlaps what it writes, offset
+        // 1 and length 14.
+        let run = [0x01, 0x03, 0xB0, 0x02, 0x61, 0x0B, 0x00];
+        assert_eq!(MacroReport::decompress(&run).unwrap(), b"aaaaaaaaaaaaaaa");
+    }
+
+    #[test]
+    fn decompress_raw_chunk_and_errors() {
+        let mut raw = vec![0x01, 0xFF, 0x3F];
+        raw.extend([b'x'; 4096]);
+        assert_eq!(MacroReport::decompress(&raw).unwrap(), vec![b'x'; 4096]);
+
+        assert!(MacroReport::decompress(&[0x00, 0x19, 0xB0]).is_err());
+        // A copy token as the very first token has nothing to copy from.
+        assert!(MacroReport::decompress(&[0x01, 0x03, 0xB0, 0x01, 0x00, 0x00]).is_err());
+    }
+
+    #[test]
+    fn reads_vba_project() {
+        let source = b"Attribute VB_Name = \"Module1\"\r\nSub AutoOpen()\r\n    Shell \"calc.exe\"\r\nEnd Sub\r\n";
+        let mut dir = Vec::new();
+        dir.extend(record(0x0003, &1252u16.to_le_bytes()));
+        // PROJECTVERSION, declared as 4 bytes and followed by 6.
+        dir.extend(record(0x0009, &[1, 0, 0, 0]));
+        dir.extend([0, 0]);
+        dir.extend(record(0x0019, b"Module1"));
+        dir.extend(record(0x001A, b"Module1"));
+        dir.extend(record(0x0031, &3u32.to_le_bytes()));
+        let mut module = vec![0xAA; 3];
+        module.extend(container(source));
+
+        let mut cfb = CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+        cfb.create_storage("/VBA").unwrap();
+        cfb.create_stream("/VBA/dir")
+            .unwrap()
+            .write_all(&container(&dir))
+            .unwrap();
+        cfb.create_stream("/VBA/Module1")
+            .unwrap()
+            .write_all(&module)
+            .unwrap();
+        cfb.flush().unwrap();
+        let data = cfb.into_inner().into_inner();
+
+        let report = MacroReport::inspect(&data, Some(&FileType::OLE)).unwrap();
+        assert_eq!(report.modules.len(), 1);
+        assert_eq!(report.modules[0].name, "Module1");
+        assert_eq!(report.modules[0].source.as_bytes(), source);
+        assert_eq!(report.auto_exec, ["AutoOpen"]);
+        assert_eq!(report.suspicious, ["Shell"]);
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn finds_macro_sheets() {
+        let biff = |id: u16, body: &[u8]| {
+            let mut out = id.to_le_bytes().to_vec();
+            out.extend_from_slice(&(body.len() as u16).to_le_bytes());
+            out.extend_from_slice(body);
+            out
+        };
+        let sheet = |state: u8, kind: u8, name: &[u8], wide: bool| {
+            let count = if wide { name.len() / 2 } else { name.len() };
+            let mut body = vec![0, 0, 0, 0, state, kind, count as u8, u8::from(wide)];
+            body.extend_from_slice(name);
+            biff(0x0085, &body)
+        };
+
+        let mut stream = biff(0x0809, &[0; 16]);
+        stream.extend(sheet(0, 0, b"Sheet1", false));
+        stream.extend(sheet(2, 1, b"Macro1", false));
+        stream.extend(sheet(1, 1, &[b'M', 0, b'2', 0], true));
+        stream.extend(biff(0x000A, &[]));
+        // Records after the globals EOF belong to the sheets.
+        stream.extend(sheet(0, 1, b"Late", false));
+
+        assert_eq!(
+            MacroReport::macro_sheets(&stream),
+            ["Macro1 (very hidden)", "M2 (hidden)"]
+        );
+    }
+}
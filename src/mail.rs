@@ -1,92 +1,131 @@
-use mailparse::parse_mail;
+use crate::{
+    decode::Decode,
+    email::ParsedEmail,
+    error::{Error, Result},
+    headers::HeaderMap,
+    mailbox::Mailbox,
+    mime::MimePart,
+    msg::Msg,
+    redirect::RedirectTable,
+};
+use mailparse::{parse_headers, parse_mail};
 use std::{
-    collections::HashMap,
-    fs::{self},
-    path::PathBuf,
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
 };
 
 pub struct Mail {
     eml_path: PathBuf,
+    redirects: RedirectTable,
 }
 
 impl Mail {
     pub fn new(input_file: PathBuf) -> Self {
         Self {
             eml_path: input_file,
+            redirects: RedirectTable::default(),
         }
     }
 
-    pub fn get_content(&self) -> String {
-        let content = fs::read_to_string(&self.eml_path);
-
-        match content {
-            Ok(data) => return data,
-            Err(err) => {
-                eprintln!("Unable to read data from the eml file:\n{err}");
-                panic!("{err}")
-            }
-        }
+    /// Replaces the built-in redirector table used to unwrap body URLs.
+    pub fn with_redirects(mut self, redirects: RedirectTable) -> Self {
+        self.redirects = redirects;
+        self
     }
 
-    pub fn parse(
-        &self,
-        data: &[u8],
-    ) -> (
-        HashMap<String, String>,
-        Vec<HashMap<String, String>>,
-        Vec<String>,
-    ) {
-        let parsed_mail = parse_mail(data);
-        let mut main_headers = HashMap::<String, String>::new();
-        let mut body_headers_list = Vec::<HashMap<String, String>>::new();
-        let mut body_content = Vec::<String>::new();
+    /// Reads the raw bytes of the input file. The message is not required to be
+    /// valid UTF-8, 8bit and binary parts are decoded later by their charset.
+    pub fn get_content(&self) -> Result<Vec<u8>> {
+        let content = if self.eml_path == Path::new(Self::STDIN) {
+            let mut data = Vec::new();
+            io::stdin().read_to_end(&mut data).map(|_| data)
+        } else {
+            fs::read(&self.eml_path)
+        };
+
+        content.map_err(|source| Error::Io {
+            path: self.eml_path.to_owned(),
+            source,
+        })
+    }
 
-        let (headers, sub_parts, _ctype) = match parsed_mail {
-            Ok(p) => (p.headers, p.subparts, p.ctype.params),
-            Err(err) => {
-                eprintln!("Unable to Parse the eml file:\n{err}");
-                panic!("{err}")
+    /// Returns every message contained in the input. A single .eml or .msg
+    /// file gives one message, mbox files and Maildir directories give one
+    /// entry per stored message. An mbox or Maildir without any message is
+    /// an error rather than an empty batch.
+    pub fn get_messages(&self) -> Result<Vec<Vec<u8>>> {
+        let messages = if Mailbox::is_maildir(&self.eml_path) {
+            Mailbox::read_maildir(&self.eml_path)?
+                .into_iter()
+                .map(|(_, data)| data)
+                .collect()
+        } else {
+            let data = self.get_content()?;
+            if !Mailbox::is_mbox(&data) {
+                return Ok(vec![data]);
             }
+            Mailbox::split_mbox(&data)
         };
 
-        for h in headers {
-            let key = h.get_key();
-            let value = h.get_value();
-            Self::add_to_map(&mut main_headers, key, value);
+        if messages.is_empty() {
+            return Err(Error::Parse(format!(
+                "no messages found in {}",
+                self.eml_path.display()
+            )));
         }
+        Ok(messages)
+    }
 
-        for sp in sub_parts.iter() {
-            let headers = sp.get_headers();
-            let body = sp.get_body();
+    /// Parses input that holds only a header block. Text copied from Outlook
+    /// or Gmail often has a banner or summary before the real headers, that
+    /// is skipped up to the first trace header.
+    pub fn parse_headers_only(&self, data: &[u8]) -> Result<ParsedEmail> {
+        let text = Decode::charset_decode(None, data);
+        let mut lines: Vec<&str> = text.lines().collect();
 
-            match body {
-                Ok(data) => body_content.push(data),
-                Err(err) => {
-                    eprintln!("Error while parsing the body {err}");
-                }
-            }
+        let first_trace = lines.iter().position(|line| {
+            let lower = line.to_ascii_lowercase();
+            Self::TRACE_HEADERS.iter().any(|h| lower.starts_with(h))
+        });
+        match first_trace {
+            Some(start) => lines.drain(..start),
+            None => lines.drain(..lines.iter().take_while(|l| !l.contains(':')).count()),
+        };
 
-            for h in headers {
-                let key = h.get_key();
-                let value = h.get_value();
-                let mut body_headers = HashMap::<String, String>::new();
-                Self::add_to_map(&mut body_headers, key, value);
-                body_headers_list.push(body_headers);
-            }
+        let block = lines.join("\r\n") + "\r\n\r\n";
+        let (parsed, _) = parse_headers(block.as_bytes())?;
+        if parsed.is_empty() {
+            return Err(Error::Parse(String::from("no headers found in the input")));
         }
-        return (main_headers, body_headers_list, body_content);
+        Ok(ParsedEmail::from_headers(HeaderMap::from(
+            parsed.as_slice(),
+        )))
     }
 
-    fn add_to_map(h_map: &mut HashMap<String, String>, key: String, value: String) {
-        let pos = h_map.insert(key.to_owned(), value.to_owned());
-
-        match pos {
-            Some(old_value) => {
-                println!("The key: {key} exists in the map.\nOld value: {old_value} is replaced by \n->\t New Value:{value}");
-            }
-            None => {
-                println!("New value inserted with Key: {key}");
-            }
-        }
+    pub fn parse(&self, data: &[u8]) -> Result<ParsedEmail> {
+        // Outlook .msg files are picked by their compound file signature, any
+        // other input is handed to the MIME parser.
+        let tree = if Msg::is_msg(data) {
+            Msg::parse(data)?
+        } else {
+            let parsed_mail = parse_mail(data)?;
+            MimePart::from_parsed(&parsed_mail, String::new())
+        };
+        Ok(ParsedEmail::from_tree(tree, &self.redirects))
     }
 }
+
+impl Mail {
+    /// Input path that reads the message from standard input.
+    pub const STDIN: &'static str = "-";
+
+    const TRACE_HEADERS: [&'static str; 6] = [
+        "received:",
+        "return-path:",
+        "delivered-to:",
+        "x-received:",
+        "arc-seal:",
+        "authentication-results:",
+    ];
+}
@@ -0,0 +1,138 @@
+use crate::error::{Error, Result};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Splits mailbox exports into individual raw messages. Supports mbox files
+/// (Thunderbird, Google Takeout) and Maildir directories.
+pub struct Mailbox;
+
+impl Mailbox {
+    /// An mbox file starts with a `From ` separator line, a plain message
+    /// never does since header names are followed by a colon.
+    pub fn is_mbox(data: &[u8]) -> bool {
+        data.starts_with(b"From ")
+    }
+
+    pub fn is_maildir(path: &Path) -> bool {
+        path.join("cur").is_dir() || path.join("new").is_dir()
+    }
+
+    /// Splits on `From ` separator lines and undoes the mboxrd quoting of
+    /// body lines that start with `From `.
+    pub fn split_mbox(data: &[u8]) -> Vec<Vec<u8>> {
+        let mut messages = Vec::new();
+        let mut current: Option<Vec<u8>> = None;
+        let mut previous_blank = true;
+
+        for line in data.split_inclusive(|b| *b == b'\n') {
+            if previous_blank && line.starts_with(b"From ") {
+                if let Some(message) = current.take() {
+                    messages.push(message);
+                }
+                current = Some(Vec::new());
+                previous_blank = false;
+                continue;
+            }
+
+            let trimmed = line.strip_suffix(b"\n").unwrap_or(line);
+            let trimmed = trimmed.strip_suffix(b"\r").unwrap_or(trimmed);
+            previous_blank = trimmed.is_empty();
+
+            let unquoted = match line.iter().position(|b| *b != b'>') {
+                Some(n) if n > 0 && line[n..].starts_with(b"From ") => &line[1..],
+                _ => line,
+            };
+            if let Some(message) = current.as_mut() {
+                message.extend_from_slice(unquoted);
+            }
+        }
+
+        if let Some(message) = current {
+            messages.push(message);
+        }
+        // A separator with nothing after it is not a message.
+        messages.retain(|m| !m.iter().all(u8::is_ascii_whitespace));
+        messages
+    }
+
+    /// Reads every message in the `new` and `cur` folders of a Maildir, in
+    /// file name order which for Maildir is delivery order.
+    pub fn read_maildir(path: &Path) -> Result<Vec<(PathBuf, Vec<u8>)>> {
+        let mut files = Vec::new();
+        for folder in ["cur", "new"] {
+            let folder = path.join(folder);
+            if !folder.is_dir() {
+                continue;
+            }
+            let entries = fs::read_dir(&folder).map_err(|source| Error::Io {
+                path: folder.to_owned(),
+                source,
+            })?;
+            files.extend(
+                entries
+                    .filter_map(|e| e.ok())
+                    .map(|e| e.path())
+                    .filter(|p| p.is_file()),
+            );
+        }
+        files.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+
+        let mut messages = Vec::new();
+        for file in files {
+            let data = fs::read(&file).map_err(|source| Error::Io {
+                path: file.to_owned(),
+                source,
+            })?;
+            messages.push((file, data));
+        }
+        Ok(messages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_mboxrd() {
+        let mbox = b"From alice@example.com Mon Jan  1 00:00:00 2024\n\
+Subject: one\n\
+\n\
+>From the start of a line\n\
+>>From quoted twice\n\
+> From is not quoting\n\
+\n\
+From bob@example.com Tue Jan  2 00:00:00 2024\r\n\
+Subject: two\r\n\
+\r\n\
+>From here on, not a separator\r\n";
+
+        let messages = Mailbox::split_mbox(mbox);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(
+            messages[0],
+            b"Subject: one\n\
+\n\
+From the start of a line\n\
+>From quoted twice\n\
+> From is not quoting\n\
+\n"
+        );
+        assert_eq!(
+            messages[1],
+            b"Subject: two\r\n\r\nFrom here on, not a separator\r\n"
+        );
+    }
+
+    #[test]
+    fn mbox_detection() {
+        assert!(Mailbox::is_mbox(
+            b"From MAILER-DAEMON Thu Jan  1 00:00:00 1970\n"
+        ));
+        assert!(!Mailbox::is_mbox(b"From: alice@example.com\n"));
+        assert!(Mailbox::split_mbox(b"").is_empty());
+        assert!(Mailbox::split_mbox(b"From x Thu Jan  1 00:00:00 1970\n\n").is_empty());
+    }
+}
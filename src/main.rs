@@ -1,30 +1,63 @@
-mod mail;
-mod newdoc;
-
-use clap::{arg, Parser};
-use mail::Mail;
-use newdoc::NewDocx;
-use std::path::PathBuf;
+use clap::{error::ErrorKind, CommandFactory, Parser};
+use eml_2_docx::{Extractor, Mail, NewDocx, RedirectTable, Result};
+use std::{
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
 
 #[derive(Parser, Debug)]
 #[command(
     version = "1.0.0",
-    about = "The application is designed to parse Outlook (.eml) files and generate structured Word document based on the extracted email headers."
+    about = "The application is designed to parse email (.eml), Outlook (.msg), mbox and Maildir inputs and generate structured Word document based on the extracted email headers.",
+    after_help = "Exit codes:\n  0  Report(s) written\n  2  Invalid command line\n  3  Input could not be read\n  4  Input could not be parsed\n  5  Word document could not be built\n  6  Word document or extracted files could not be written\n  7  Redirector table could not be read or parsed"
 )]
 struct Args {
     #[arg(
         short = 'i',
         long = "in-file",
         value_name = "FILE PATH",
-        help = "Input file"
+        help = "Input file (.eml, .msg or mbox), Maildir directory, or - for stdin"
     )]
     in_file: String,
 
+    #[arg(
+        long = "headers-only",
+        help = "Input is a bare header block, e.g. Outlook's Internet headers or Gmail's Show original"
+    )]
+    headers_only: bool,
+
+    #[arg(
+        long = "redirectors",
+        value_name = "FILE PATH",
+        help = "Extra open redirectors to unwrap, one `host path params encoding name` entry per line"
+    )]
+    redirectors: Option<PathBuf>,
+
+    #[arg(
+        long = "defang",
+        help = "Defang URLs, domains, IPs and email addresses in the report (hxxps://evil[.]com)"
+    )]
+    defang: bool,
+
+    #[arg(
+        long = "extract-dir",
+        value_name = "DIR PATH",
+        help = "Write every decoded attachment and inline part here, named by SHA-256, with a manifest.json"
+    )]
+    extract_dir: Option<PathBuf>,
+
+    #[arg(
+        long = "zip",
+        requires = "extract_dir",
+        help = "Pack the extracted files into attachments.zip, AES encrypted with the password `infected`"
+    )]
+    zip: bool,
+
     #[arg(
         short = 'o',
         long = "out-file",
         value_name = "FILE PATH",
-        help = "Output file"
+        help = "Output file, or - for stdout"
     )]
     out_file: String,
 
@@ -37,17 +70,122 @@ struct Args {
     i_num: String,
 }
 
-fn main() {
+fn main() -> ExitCode {
     let args = Args::parse();
 
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::from(err.exit_code())
+        }
+    }
+}
+
+fn run(args: Args) -> Result<()> {
     let in_file = args.in_file;
-    let out_file = args.out_file;
+    let out_file = PathBuf::from(args.out_file);
     let incident_number = args.i_num;
-    let eml = Mail::new(PathBuf::from(in_file));
-    let data = eml.get_content();
-    let (headers, body_headers, _content) = eml.parse(data.as_bytes());
+    let mut redirects = RedirectTable::default();
+    if let Some(path) = &args.redirectors {
+        redirects.load(path)?;
+    }
+    let eml = Mail::new(PathBuf::from(in_file)).with_redirects(redirects);
+    let messages = if args.headers_only {
+        vec![eml.get_content()?]
+    } else {
+        eml.get_messages()?
+    };
+    let numbered = messages.len() > 1;
+
+    // Only known once the input is read, but still a command line mistake.
+    if numbered && out_file == Path::new(NewDocx::STDOUT) {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "a mailbox produces several reports, give an output file instead of stdout",
+            )
+            .exit();
+    }
+
+    // A broken message in a mailbox should not stop the rest of the batch, the
+    // first failure is still reported through the exit code.
+    let mut first_error = None;
+    for (i, data) in messages.iter().enumerate() {
+        // Mailbox inputs produce one report per message, numbered from 1.
+        let (docx_path, i_number) = if numbered {
+            (
+                numbered_path(&out_file, i + 1),
+                format!("{}-{}", incident_number, i + 1),
+            )
+        } else {
+            (out_file.clone(), incident_number.clone())
+        };
+
+        let new_docx = NewDocx::new(docx_path, i_number).with_defang(args.defang);
+        // Each message of a mailbox gets its own folder for the manifest.
+        let extractor = args.extract_dir.as_ref().map(|dir| {
+            let dir = if numbered {
+                dir.join((i + 1).to_string())
+            } else {
+                dir.clone()
+            };
+            Extractor::new(dir, args.zip)
+        });
+        if let Err(err) = report(&eml, data, args.headers_only, &new_docx, extractor.as_ref()) {
+            if !numbered {
+                return Err(err);
+            }
+            eprintln!("Message {}: {err}", i + 1);
+            first_error.get_or_insert(err);
+        }
+    }
+
+    match first_error {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+fn report(
+    eml: &Mail,
+    data: &[u8],
+    headers_only: bool,
+    new_docx: &NewDocx,
+    extractor: Option<&Extractor>,
+) -> Result<()> {
+    let email = if headers_only {
+        eml.parse_headers_only(data)?
+    } else {
+        eml.parse(data)?
+    };
+    for warning in &email.warnings {
+        eprintln!("Warning: {warning}");
+    }
+
+    if let Some(extractor) = extractor {
+        let files = extractor.extract(&email)?;
+        // Status goes to stderr, stdout may be carrying the report itself.
+        eprintln!(
+            "Extracted {} part(s) to {}",
+            files.len(),
+            extractor.dir().display()
+        );
+    }
+
+    let doc = new_docx.generate_content(&email);
+    new_docx.create_docx(doc)
+}
 
-    let new_docx = NewDocx::new(PathBuf::from(out_file), incident_number);
-    let doc = new_docx.generate_content(headers, body_headers);
-    new_docx.create_docx(doc);
+/// `report.docx` becomes `report-3.docx` for the third message of a mailbox.
+fn numbered_path(path: &Path, number: usize) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = match path.extension() {
+        Some(ext) => format!("{stem}-{number}.{}", ext.to_string_lossy()),
+        None => format!("{stem}-{number}"),
+    };
+    path.with_file_name(name)
 }
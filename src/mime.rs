@@ -0,0 +1,157 @@
+use crate::{decode::Decode, headers::HeaderMap};
+use mailparse::{parse_mail, DispositionType, ParsedMail};
+
+/// One node of the MIME tree. Paths follow the IMAP section numbering, the
+/// message itself has an empty path and its children are `1`, `2`, ... with
+/// nested parts written as `1.2.1`.
+#[derive(Debug, Clone)]
+pub struct MimePart {
+    pub path: String,
+    pub content_type: String,
+    pub disposition: Option<String>,
+    pub filename: Option<String>,
+    pub headers: HeaderMap,
+    /// Transfer-decoded body. Empty for multipart containers.
+    pub body: Vec<u8>,
+    /// Charset-decoded body, only set for `text/*` parts.
+    pub text: Option<String>,
+    pub children: Vec<MimePart>,
+    /// Problems met while decoding this part. The part is kept with whatever
+    /// could be read.
+    pub warnings: Vec<String>,
+}
+
+impl MimePart {
+    pub fn from_parsed(part: &ParsedMail, path: String) -> Self {
+        let disposition = part
+            .headers
+            .iter()
+            .any(|h| h.get_key_ref().eq_ignore_ascii_case("Content-Disposition"))
+            .then(|| match part.get_content_disposition().disposition {
+                DispositionType::Inline => String::from("inline"),
+                DispositionType::Attachment => String::from("attachment"),
+                DispositionType::FormData => String::from("form-data"),
+                DispositionType::Extension(ext) => ext,
+            });
+
+        let filename = Self::filename(part);
+        let mut warnings = Vec::new();
+
+        let body = if part.subparts.is_empty() {
+            match part.get_body_raw() {
+                Ok(data) => data,
+                Err(err) => {
+                    warnings.push(format!("body could not be decoded, {err}"));
+                    Vec::new()
+                }
+            }
+        } else {
+            Vec::new()
+        };
+
+        let text = if part.ctype.mimetype.starts_with("text/") {
+            match part.get_body() {
+                Ok(data) => Some(data),
+                Err(err) => {
+                    warnings.push(format!("text could not be decoded, {err}"));
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let mut children = Vec::new();
+        for (i, sp) in part.subparts.iter().enumerate() {
+            children.push(Self::from_parsed(sp, Self::child_path(&path, i + 1)));
+        }
+
+        // mailparse leaves attached messages as an opaque body, parse them so
+        // their parts are part of the same tree.
+        if Self::looks_like_message(&part.ctype.mimetype, filename.as_deref()) {
+            match parse_mail(&body) {
+                Ok(inner) => children.push(Self::from_parsed(&inner, Self::child_path(&path, 1))),
+                Err(err) => warnings.push(format!("attached message could not be parsed, {err}")),
+            }
+        }
+
+        Self {
+            content_type: part.ctype.mimetype.to_owned(),
+            disposition,
+            filename,
+            headers: HeaderMap::from(part.headers.as_slice()),
+            body,
+            text,
+            children,
+            warnings,
+            path,
+        }
+    }
+
+    /// Attachment name from Content-Disposition `filename` or, failing that,
+    /// Content-Type `name`, with RFC 2047 and RFC 2231 encodings decoded.
+    fn filename(part: &ParsedMail) -> Option<String> {
+        let raw_header = |key: &str| {
+            part.headers
+                .iter()
+                .find(|h| h.get_key_ref().eq_ignore_ascii_case(key))
+                .map(Decode::header_raw)
+        };
+
+        let from_disposition =
+            raw_header("Content-Disposition").and_then(|v| Decode::params(&v).1.remove("filename"));
+        from_disposition
+            .or_else(|| {
+                raw_header("Content-Type").and_then(|v| Decode::params(&v).1.remove("name"))
+            })
+            .filter(|f| !f.trim().is_empty())
+    }
+
+    fn looks_like_message(content_type: &str, filename: Option<&str>) -> bool {
+        content_type == "message/rfc822"
+            || (content_type == "application/octet-stream"
+                && filename.is_some_and(|f| f.to_ascii_lowercase().ends_with(".eml")))
+    }
+
+    /// True for attached messages, including `.eml` files sent as plain
+    /// binary attachments.
+    pub fn is_message(&self) -> bool {
+        Self::looks_like_message(&self.content_type, self.filename.as_deref())
+    }
+
+    fn child_path(parent: &str, index: usize) -> String {
+        if parent.is_empty() {
+            index.to_string()
+        } else {
+            format!("{parent}.{index}")
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.body.len()
+    }
+
+    /// A part is treated as an attachment when it is explicitly marked as one,
+    /// carries a filename, or is an attached message.
+    pub fn is_attachment(&self) -> bool {
+        self.disposition.as_deref() == Some("attachment")
+            || self.filename.is_some()
+            || (self.is_message() && !self.path.is_empty())
+    }
+
+    /// Depth-first, pre-order walk over this part and all of its descendants.
+    pub fn flatten(&self) -> Vec<&MimePart> {
+        let mut parts = vec![self];
+        for child in &self.children {
+            parts.extend(child.flatten());
+        }
+        parts
+    }
+
+    pub fn attachments(&self) -> Vec<&MimePart> {
+        self.flatten()
+            .into_iter()
+            .filter(|p| p.is_attachment())
+            .collect()
+    }
+}
@@ -0,0 +1,526 @@
+use crate::{
+    error::{Error, Result},
+    headers::HeaderMap,
+    mime::MimePart,
+};
+use cfb::CompoundFile;
+use encoding_rs::WINDOWS_1252;
+use mailparse::parse_headers;
+use std::{
+    io::{Cursor, Read},
+    path::{Path, PathBuf},
+};
+
+/// Reader for Outlook .msg files. The compound file is turned into the same
+/// `MimePart` tree that is produced for .eml input, so the rest of the
+/// pipeline does not need to know where the message came from.
+pub struct Msg {
+    cfb: CompoundFile<Cursor<Vec<u8>>>,
+}
+
+impl Msg {
+    /// Every OLE compound file starts with this signature.
+    pub const SIGNATURE: [u8; 8] = [0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+
+    pub fn is_msg(data: &[u8]) -> bool {
+        data.starts_with(&Self::SIGNATURE)
+    }
+
+    pub fn parse(data: &[u8]) -> Result<MimePart> {
+        let cfb = CompoundFile::open(Cursor::new(data.to_vec()))
+            .map_err(|err| Error::Parse(format!("not a valid msg file, {err}")))?;
+
+        // Word and Excel documents are compound files too, a message always
+        // has a property stream or some property streams at the root.
+        let is_message = cfb.is_stream("/__properties_version1.0")
+            || cfb
+                .read_root_storage()
+                .any(|e| e.is_stream() && e.name().starts_with("__substg1.0_"));
+        if !is_message {
+            return Err(Error::Parse(String::from(
+                "compound file is not an Outlook message",
+            )));
+        }
+
+        let mut msg = Self { cfb };
+        Ok(msg.message(Path::new("/"), String::new(), Self::TOP_HEADER_LEN))
+    }
+
+    /// Builds the tree for the message stored in `storage`. Embedded messages
+    /// use the same layout as the top level one, only with a shorter property
+    /// stream header.
+    fn message(&mut self, storage: &Path, path: String, header_len: usize) -> MimePart {
+        let props = self.properties(storage, header_len);
+        let recipients = self.recipients(storage);
+
+        let headers = match self.string_prop(storage, Self::PR_TRANSPORT_MESSAGE_HEADERS) {
+            Some(raw) => match parse_headers(raw.as_bytes()) {
+                Ok((parsed, _)) => HeaderMap::from(parsed.as_slice()),
+                Err(_) => self.synthetic_headers(storage, &recipients, &props),
+            },
+            None => self.synthetic_headers(storage, &recipients, &props),
+        };
+
+        let mut children = Vec::new();
+        let child_path = |index: usize| {
+            if path.is_empty() {
+                index.to_string()
+            } else {
+                format!("{path}.{index}")
+            }
+        };
+
+        if let Some(text) = self.string_prop(storage, Self::PR_BODY) {
+            let path = child_path(children.len() + 1);
+            children.push(Self::text_part(path, "text/plain", text));
+        }
+
+        let html = self
+            .binary_prop(storage, Self::PR_HTML)
+            .map(|b| Self::decode_8bit(&b))
+            .or_else(|| self.string_prop(storage, Self::PR_HTML));
+        if let Some(text) = html {
+            let path = child_path(children.len() + 1);
+            children.push(Self::text_part(path, "text/html", text));
+        }
+
+        if let Some(compressed) = self.binary_prop(storage, Self::PR_RTF_COMPRESSED) {
+            if let Some(rtf) = Self::decompress_rtf(&compressed) {
+                let path = child_path(children.len() + 1);
+                children.push(Self::text_part(path, "text/rtf", Self::decode_8bit(&rtf)));
+            }
+        }
+
+        for attachment in self.sub_storages(storage, "__attach_version1.0_") {
+            let path = child_path(children.len() + 1);
+            children.push(self.attachment(&attachment, path));
+        }
+
+        MimePart {
+            path,
+            content_type: String::from("multipart/mixed"),
+            disposition: None,
+            filename: None,
+            headers,
+            body: Vec::new(),
+            text: None,
+            children,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Messages that never left the Exchange organization have no transport
+    /// headers, rebuild the basic ones from the MAPI properties.
+    fn synthetic_headers(
+        &mut self,
+        storage: &Path,
+        recipients: &[(u32, String)],
+        props: &[(u16, u64)],
+    ) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+
+        let name = self
+            .string_prop(storage, Self::PR_SENDER_NAME)
+            .or_else(|| self.string_prop(storage, Self::PR_SENT_REPRESENTING_NAME));
+        let address = self
+            .string_prop(storage, Self::PR_SENDER_SMTP_ADDRESS)
+            .or_else(|| self.string_prop(storage, Self::PR_SENDER_EMAIL_ADDRESS))
+            .or_else(|| self.string_prop(storage, Self::PR_SENT_REPRESENTING_EMAIL_ADDRESS));
+        match (name, address) {
+            (Some(n), Some(a)) => headers.push(String::from("From"), format!("\"{n}\" <{a}>")),
+            (None, Some(a)) => headers.push(String::from("From"), a),
+            (Some(n), None) => headers.push(String::from("From"), n),
+            (None, None) => {}
+        }
+
+        for (kind, key) in [(1, "To"), (2, "Cc")] {
+            let list: Vec<&str> = recipients
+                .iter()
+                .filter(|(k, _)| *k == kind)
+                .map(|(_, r)| r.as_str())
+                .collect();
+            if !list.is_empty() {
+                headers.push(key.to_string(), list.join(", "));
+            }
+        }
+
+        if let Some(subject) = self.string_prop(storage, Self::PR_SUBJECT) {
+            headers.push(String::from("Subject"), subject);
+        }
+
+        let date = [Self::PR_CLIENT_SUBMIT_TIME, Self::PR_MESSAGE_DELIVERY_TIME]
+            .iter()
+            .find_map(|id| props.iter().find(|(p, _)| p == id))
+            .map(|(_, v)| Self::filetime_to_rfc2822(*v));
+        if let Some(date) = date {
+            headers.push(String::from("Date"), date);
+        }
+
+        headers
+    }
+
+    /// Recipient type (1 = To, 2 = Cc, 3 = Bcc) and formatted address of every
+    /// recipient of the message.
+    fn recipients(&mut self, storage: &Path) -> Vec<(u32, String)> {
+        let mut recipients = Vec::new();
+        for recip in self.sub_storages(storage, "__recip_version1.0_") {
+            let props = self.properties(&recip, Self::SUB_HEADER_LEN);
+            let kind = props
+                .iter()
+                .find(|(id, _)| *id == Self::PR_RECIPIENT_TYPE)
+                .map(|(_, v)| *v as u32)
+                .unwrap_or(1);
+
+            let name = self.string_prop(&recip, Self::PR_DISPLAY_NAME);
+            let address = self
+                .string_prop(&recip, Self::PR_SMTP_ADDRESS)
+                .or_else(|| self.string_prop(&recip, Self::PR_EMAIL_ADDRESS));
+            let formatted = match (name, address) {
+                (Some(n), Some(a)) if n != a => format!("\"{n}\" <{a}>"),
+                (_, Some(a)) => a,
+                (Some(n), None) => n,
+                (None, None) => continue,
+            };
+            recipients.push((kind, formatted));
+        }
+        recipients
+    }
+
+    fn attachment(&mut self, storage: &Path, path: String) -> MimePart {
+        let filename = self
+            .string_prop(storage, Self::PR_ATTACH_LONG_FILENAME)
+            .or_else(|| self.string_prop(storage, Self::PR_ATTACH_FILENAME))
+            .or_else(|| self.string_prop(storage, Self::PR_DISPLAY_NAME));
+        let content_type = self
+            .string_prop(storage, Self::PR_ATTACH_MIME_TAG)
+            .map(|m| m.to_ascii_lowercase())
+            .unwrap_or_else(|| String::from("application/octet-stream"));
+        let disposition = if self
+            .string_prop(storage, Self::PR_ATTACH_CONTENT_ID)
+            .is_some()
+        {
+            "inline"
+        } else {
+            "attachment"
+        };
+
+        let mut headers = HeaderMap::new();
+        headers.push(String::from("Content-Type"), content_type.to_owned());
+        if let Some(cid) = self.string_prop(storage, Self::PR_ATTACH_CONTENT_ID) {
+            headers.push(String::from("Content-ID"), format!("<{cid}>"));
+        }
+
+        // Attached Outlook items are stored as a nested message storage
+        // instead of a data stream.
+        let embedded = storage.join(format!("__substg1.0_{:04X}000D", Self::PR_ATTACH_DATA));
+        if self.cfb.is_storage(&embedded) {
+            let inner = self.message(&embedded, format!("{path}.1"), Self::EMBEDDED_HEADER_LEN);
+            return MimePart {
+                path,
+                content_type: String::from("message/rfc822"),
+                disposition: Some(disposition.to_string()),
+                filename,
+                headers,
+                body: Vec::new(),
+                text: None,
+                children: vec![inner],
+                warnings: Vec::new(),
+            };
+        }
+
+        let body = self
+            .binary_prop(storage, Self::PR_ATTACH_DATA)
+            .unwrap_or_default();
+
+        MimePart {
+            path,
+            content_type,
+            disposition: Some(disposition.to_string()),
+            filename,
+            headers,
+            body,
+            text: None,
+            children: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    fn text_part(path: String, content_type: &str, text: String) -> MimePart {
+        let mut headers = HeaderMap::new();
+        headers.push(
+            String::from("Content-Type"),
+            format!("{content_type}; charset=utf-8"),
+        );
+        MimePart {
+            path,
+            content_type: content_type.to_string(),
+            disposition: None,
+            filename: None,
+            headers,
+            body: text.as_bytes().to_vec(),
+            text: Some(text),
+            children: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    fn sub_storages(&self, storage: &Path, prefix: &str) -> Vec<PathBuf> {
+        let entries = match self.cfb.read_storage(storage) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+        let mut storages: Vec<PathBuf> = entries
+            .filter(|e| e.is_storage() && e.name().starts_with(prefix))
+            .map(|e| e.path().to_path_buf())
+            .collect();
+        storages.sort();
+        storages
+    }
+
+    fn read_stream(&mut self, path: &Path) -> Option<Vec<u8>> {
+        let mut stream = self.cfb.open_stream(path).ok()?;
+        let mut data = Vec::new();
+        stream.read_to_end(&mut data).ok()?;
+        Some(data)
+    }
+
+    fn string_prop(&mut self, storage: &Path, id: u16) -> Option<String> {
+        let unicode = storage.join(format!("__substg1.0_{id:04X}001F"));
+        if let Some(data) = self.read_stream(&unicode) {
+            let units: Vec<u16> = data
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .collect();
+            let text = String::from_utf16_lossy(&units);
+            return Some(text.trim_end_matches('\0').to_string());
+        }
+
+        let ansi = storage.join(format!("__substg1.0_{id:04X}001E"));
+        self.read_stream(&ansi)
+            .map(|data| Self::decode_8bit(&data).trim_end_matches('\0').to_string())
+    }
+
+    fn binary_prop(&mut self, storage: &Path, id: u16) -> Option<Vec<u8>> {
+        self.read_stream(&storage.join(format!("__substg1.0_{id:04X}0102")))
+    }
+
+    /// Fixed size properties live in one stream of 16 byte entries, returns the
+    /// property id and the raw 8 byte value of each.
+    fn properties(&mut self, storage: &Path, header_len: usize) -> Vec<(u16, u64)> {
+        let data = match self.read_stream(&storage.join("__properties_version1.0")) {
+            Some(d) => d,
+            None => return Vec::new(),
+        };
+        data.get(header_len..)
+            .unwrap_or_default()
+            .chunks_exact(16)
+            .map(|entry| {
+                let id = u16::from_le_bytes([entry[2], entry[3]]);
+                let mut value = [0u8; 8];
+                value.copy_from_slice(&entry[8..16]);
+                (id, u64::from_le_bytes(value))
+            })
+            .collect()
+    }
+
+    /// 8-bit properties use the message code page, UTF-8 is tried first and
+    /// Windows-1252 is the fallback used by western Outlook installs.
+    fn decode_8bit(data: &[u8]) -> String {
+        match std::str::from_utf8(data) {
+            Ok(text) => text.to_string(),
+            Err(_) => WINDOWS_1252.decode(data).0.into_owned(),
+        }
+    }
+
+    /// Decompresses PR_RTF_COMPRESSED as described in [MS-OXRTFCP].
+    fn decompress_rtf(data: &[u8]) -> Option<Vec<u8>> {
+        let header = data.get(..16)?;
+        let raw_size = u32::from_le_bytes(header[4..8].try_into().ok()?) as usize;
+        let comp_type = &header[8..12];
+        let body = &data[16..];
+
+        if comp_type == b"MELA" {
+            return Some(body.iter().copied().take(raw_size).collect());
+        }
+        if comp_type != b"LZFu" {
+            return None;
+        }
+
+        let mut dict = [0u8; 4096];
+        dict[..Self::RTF_PREBUF.len()].copy_from_slice(Self::RTF_PREBUF);
+        let mut write_pos = Self::RTF_PREBUF.len();
+        let mut out = Vec::with_capacity(raw_size);
+        let mut pos = 0;
+
+        while pos < body.len() {
+            let control = body[pos];
+            pos += 1;
+            for bit in 0..8 {
+                if pos >= body.len() {
+                    break;
+                }
+                if control & (1 << bit) == 0 {
+                    let byte = body[pos];
+                    pos += 1;
+                    out.push(byte);
+                    dict[write_pos] = byte;
+                    write_pos = (write_pos + 1) % dict.len();
+                    continue;
+                }
+
+                let reference = u16::from_be_bytes([body[pos], *body.get(pos + 1)?]);
+                pos += 2;
+                let offset = (reference >> 4) as usize;
+                let length = (reference & 0xF) as usize + 2;
+                if offset == write_pos {
+                    return Some(out);
+                }
+                for i in 0..length {
+                    let byte = dict[(offset + i) % dict.len()];
+                    out.push(byte);
+                    dict[write_pos] = byte;
+                    write_pos = (write_pos + 1) % dict.len();
+                }
+            }
+        }
+        Some(out)
+    }
+
+    /// FILETIME counts 100ns intervals since 1601-01-01 UTC.
+    fn filetime_to_rfc2822(filetime: u64) -> String {
+        let secs = (filetime / 10_000_000) as i64 - 11_644_473_600;
+        let days = secs.div_euclid(86_400);
+        let rem = secs.rem_euclid(86_400);
+
+        // Civil date from days since the unix epoch (Howard Hinnant's algorithm).
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + i64::from(month <= 2);
+
+        let weekday = Self::WEEKDAYS[(days + 4).rem_euclid(7) as usize];
+        format!(
+            "{}, {} {} {} {:02}:{:02}:{:02} +0000",
+            weekday,
+            day,
+            Self::MONTHS[(month - 1) as usize],
+            year,
+            rem / 3600,
+            rem % 3600 / 60,
+            rem % 60
+        )
+    }
+}
+
+impl Msg {
+    const TOP_HEADER_LEN: usize = 32;
+    const EMBEDDED_HEADER_LEN: usize = 24;
+    const SUB_HEADER_LEN: usize = 8;
+
+    const PR_SUBJECT: u16 = 0x0037;
+    const PR_CLIENT_SUBMIT_TIME: u16 = 0x0039;
+    const PR_SENT_REPRESENTING_NAME: u16 = 0x0042;
+    const PR_SENT_REPRESENTING_EMAIL_ADDRESS: u16 = 0x0065;
+    const PR_TRANSPORT_MESSAGE_HEADERS: u16 = 0x007D;
+    const PR_RECIPIENT_TYPE: u16 = 0x0C15;
+    const PR_SENDER_NAME: u16 = 0x0C1A;
+    const PR_SENDER_EMAIL_ADDRESS: u16 = 0x0C1F;
+    const PR_MESSAGE_DELIVERY_TIME: u16 = 0x0E06;
+    const PR_BODY: u16 = 0x1000;
+    const PR_RTF_COMPRESSED: u16 = 0x1009;
+    const PR_HTML: u16 = 0x1013;
+    const PR_DISPLAY_NAME: u16 = 0x3001;
+    const PR_EMAIL_ADDRESS: u16 = 0x3003;
+    const PR_ATTACH_DATA: u16 = 0x3701;
+    const PR_ATTACH_FILENAME: u16 = 0x3704;
+    const PR_ATTACH_LONG_FILENAME: u16 = 0x3707;
+    const PR_ATTACH_MIME_TAG: u16 = 0x370E;
+    const PR_ATTACH_CONTENT_ID: u16 = 0x3712;
+    const PR_SMTP_ADDRESS: u16 = 0x39FE;
+    const PR_SENDER_SMTP_ADDRESS: u16 = 0x5D01;
+
+    const RTF_PREBUF: &'static [u8] = b"{\\rtf1\\ansi\\mac\\deff0\\deftab720{\\fonttbl;}{\\f0\\fnil \\froman \\fswiss \\fmodern \\fscript \\fdecor MS Sans SerifSymbolArialTimes New RomanCourier{\\colortbl\\red0\\green0\\blue0\r\n\\par \\pard\\plain\\f0\\fs20\\b\\i\\u\\tab\\tx";
+
+    const WEEKDAYS: [&'static str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+    const MONTHS: [&'static str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn decompress_rtf_spec_example() {
+        // [MS-OXRTFCP] 3.1.1, a literal run that ends with the end marker.
+        let compressed = [
+            0x2d, 0x00, 0x00, 0x00, 0x2b, 0x00, 0x00, 0x00, 0x4c, 0x5a, 0x46, 0x75, 0xf1, 0xc5,
+            0xc7, 0xa7, 0x03, 0x00, 0x0a, 0x00, 0x72, 0x63, 0x70, 0x67, 0x31, 0x32, 0x35, 0x42,
+            0x32, 0x0a, 0xf3, 0x20, 0x68, 0x65, 0x6c, 0x09, 0x00, 0x20, 0x62, 0x77, 0x05, 0xb0,
+            0x6c, 0x64, 0x7d, 0x0a, 0x80, 0x0f, 0xa0,
+        ];
+        let rtf = Msg::decompress_rtf(&compressed).unwrap();
+        assert_eq!(rtf, b"{\\rtf1\\ansi\\ansicpg1252\\pard hello world}\r\n");
+    }
+
+    #[test]
+    fn decompress_rtf_overlapping_reference() {
+        // [MS-OXRTFCP] 3.1.2, a reference that reads bytes it is writing.
+        let compressed = [
+            0x1a, 0x00, 0x00, 0x00, 0x1c, 0x00, 0x00, 0x00, 0x4c, 0x5a, 0x46, 0x75, 0xe2, 0xd4,
+            0x4b, 0x51, 0x41, 0x00, 0x04, 0x20, 0x57, 0x58, 0x59, 0x5a, 0x0d, 0x6e, 0x7d, 0x01,
+            0x0e, 0xb0,
+        ];
+        let rtf = Msg::decompress_rtf(&compressed).unwrap();
+        assert_eq!(rtf, b"{\\rtf1 WXYZWXYZWXYZWXYZWXYZ}");
+    }
+
+    #[test]
+    fn decompress_rtf_uncompressed_and_unknown() {
+        let mut mela = vec![0x0b, 0, 0, 0, 0x03, 0, 0, 0];
+        mela.extend_from_slice(b"MELA\0\0\0\0{\\rtf1}");
+        assert_eq!(Msg::decompress_rtf(&mela).unwrap(), b"{\\r");
+
+        let mut unknown = mela.clone();
+        unknown[8..12].copy_from_slice(b"ABCD");
+        assert!(Msg::decompress_rtf(&unknown).is_none());
+        assert!(Msg::decompress_rtf(&[0; 8]).is_none());
+    }
+
+    #[test]
+    fn filetime_dates() {
+        assert_eq!(
+            Msg::filetime_to_rfc2822(116_444_736_000_000_000),
+            "Thu, 1 Jan 1970 00:00:00 +0000"
+        );
+        assert_eq!(
+            Msg::filetime_to_rfc2822(133_000_000_000_000_000),
+            "Sat, 18 Jun 2022 04:26:40 +0000"
+        );
+        assert_eq!(
+            Msg::filetime_to_rfc2822(133_536_816_000_000_000),
+            "Thu, 29 Feb 2024 12:00:00 +0000"
+        );
+    }
+
+    #[test]
+    fn other_compound_files_are_rejected() {
+        let mut cfb = CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+        cfb.create_stream("/WordDocument")
+            .unwrap()
+            .write_all(b"not a message")
+            .unwrap();
+        cfb.flush().unwrap();
+        let data = cfb.into_inner().into_inner();
+
+        assert!(Msg::is_msg(&data));
+        assert!(matches!(Msg::parse(&data), Err(Error::Parse(_))));
+    }
+}
@@ -1,16 +1,35 @@
+use crate::{
+    address::Address,
+    archive::ArchiveListing,
+    decode::Decode,
+    defang::Defang,
+    email::{Attachment, ParsedEmail},
+    error::{Error, Result},
+    filetype::FileType,
+    harvest::HarvestReport,
+    hashes::Hashes,
+    headers::HeaderMap,
+    links::LinkMismatch,
+    macros::MacroReport,
+    mime::MimePart,
+    pdf::PdfReport,
+    smuggling::Smuggling,
+    urls::ExtractedUrl,
+};
 use docx_rs::{
     AlignmentType, Docx, LineSpacing, PageMargin, Paragraph, Run, RunFonts, Table, TableBorders,
     TableCell, TableRow,
 };
 use std::{
-    collections::HashMap,
     fs::{self},
+    io::{self, Cursor, Seek, Write},
     path::{Path, PathBuf},
 };
 
 pub struct NewDocx {
     docx_path: PathBuf,
     i_number: String,
+    defang: bool,
 }
 
 impl NewDocx {
@@ -18,73 +37,106 @@ impl NewDocx {
         Self {
             docx_path: path,
             i_number: incident_number,
+            defang: false,
         }
     }
 
-    pub fn create_docx(&self, doc: Docx) {
+    /// Writes URLs, domains, IP addresses and email addresses defanged, the
+    /// reputation lookup links stay live so they can still be opened.
+    pub fn with_defang(mut self, defang: bool) -> Self {
+        self.defang = defang;
+        self
+    }
+
+    /// Writes the report to the configured path. A path of `-` streams the
+    /// packed document to standard output instead.
+    pub fn create_docx(&self, doc: Docx) -> Result<()> {
         let path = Path::new(&self.docx_path);
-        let file = fs::File::create(path);
 
-        let file = match file {
-            Ok(f) => f,
-            Err(err) => {
-                eprintln!("Unable to create word document");
-                panic!("{err}");
-            }
-        };
+        if path == Path::new(Self::STDOUT) {
+            // Packing needs to seek, so the archive is built in memory first.
+            let mut buffer = Cursor::new(Vec::new());
+            Self::write_docx(doc, &mut buffer)?;
 
-        match doc.build().pack(file) {
-            Ok(_) => {
-                println!("Document creation completed")
-            }
-            Err(err) => {
-                eprintln!("{err}");
-                panic!("{err}")
-            }
+            let mut stdout = io::stdout().lock();
+            return stdout
+                .write_all(buffer.get_ref())
+                .and_then(|_| stdout.flush())
+                .map_err(|source| Error::Write {
+                    path: path.to_path_buf(),
+                    source,
+                });
         }
+
+        let file = fs::File::create(path).map_err(|source| Error::Write {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        Self::write_docx(doc, file)?;
+
+        println!("Document creation completed");
+        Ok(())
     }
 
-    pub fn generate_content(
-        &self,
-        headers: HashMap<String, String>,
-        b_headers: Vec<HashMap<String, String>>,
-    ) -> Docx {
-        let from_address = Self::get_values("From", &headers);
+    /// Packs the document into any seekable writer, for callers that keep the
+    /// report in memory instead of on disk.
+    pub fn write_docx<W: Write + Seek>(doc: Docx, writer: W) -> Result<()> {
+        doc.build()
+            .pack(writer)
+            .map_err(|err| Error::Render(err.to_string()))?;
+        Ok(())
+    }
+
+    pub fn generate_content(&self, email: &ParsedEmail) -> Docx {
+        let headers = &email.headers;
+        let mime_tree = &email.mime;
+        let from_address = Self::address_list(&email.addresses.from, headers.get("From"));
 
         // Extracting sender domain
-        let parts: Vec<&str> = from_address.trim().split("@").collect();
-        let sender_domain: String = if parts.len() != 2 {
+        let domains = email.addresses.from_domains();
+        let sender_domain: String = if domains.is_empty() {
             "Not able to extract domain".to_string()
         } else {
-            match parts.last() {
-                Some(d) => d.replace(">", ""),
-                None => "Not able to extract domain".to_string(),
-            }
+            domains.join(", ")
         };
+        let lookup_domain = domains.first().copied().unwrap_or(&sender_domain);
 
-        let mut have_attachments = false;
-        let mut count = 0;
-        for bh in &b_headers {
-            let c_disposition = Self::get_values("Content-Disposition", &bh);
-            if c_disposition != "NA" {
-                have_attachments = true;
-                count += 1;
-            }
-        }
+        let count = email.attached_count();
 
-        let attachments = if have_attachments {
-            format!("Yes")
+        let attachments = if email.headers_only {
+            String::from(Self::NOT_AVAILABLE)
+        } else if count > 0 {
+            String::from("Yes")
         } else {
-            format!("No")
+            String::from("No")
         };
 
-        let date = Self::get_values("Date", &headers);
-        let subject = Self::get_values("Subject", &headers);
-        let to = Self::get_values("To", &headers);
-        let return_path = Self::get_values(Self::RETURN_PATH, &headers);
-        let h_content_type = Self::get_values(Self::CONTENT_TYPE, &headers);
-        let spf = Self::get_values(Self::SPF, &headers);
-        let auth_result = Self::get_values(Self::AUTH_RESULTS, &headers);
+        // Rows that depend on the body can't be filled from a header block.
+        let body_row = if email.headers_only {
+            Self::NOT_AVAILABLE
+        } else {
+            "****"
+        };
+        let malicious_attachments = self.attachment_summary(email);
+        let urls = if email.headers_only {
+            String::from(Self::NOT_AVAILABLE)
+        } else if email.urls.is_empty() {
+            String::from("No")
+        } else {
+            format!("Yes ({})", email.urls.len())
+        };
+
+        let date = Self::get_values("Date", headers);
+        let subject = self.indicator(&Self::get_values("Subject", headers));
+        let to = self.indicator(&Self::address_list(&email.addresses.to, headers.get("To")));
+        let shown_from = self.indicator(&from_address);
+        let shown_domain = if domains.is_empty() {
+            sender_domain.to_owned()
+        } else {
+            let hosts: Vec<String> = domains.iter().map(|d| self.host(d)).collect();
+            hosts.join(", ")
+        };
         let mut docx = Docx::new();
 
         let heading = &format!("{} {}", Self::HEADING, &self.i_number);
@@ -111,7 +163,7 @@ impl NewDocx {
                 ),
                 Self::table_row(
                     Self::SENDER,
-                    &from_address,
+                    &shown_from,
                     Self::DEFAULT_BLACK,
                     Self::REGULAR_SIZE,
                 ),
@@ -123,7 +175,7 @@ impl NewDocx {
                 ),
                 Self::table_row(
                     Self::DOMAIN,
-                    &sender_domain,
+                    &shown_domain,
                     Self::DEFAULT_BLACK,
                     Self::REGULAR_SIZE,
                 ),
@@ -145,11 +197,16 @@ impl NewDocx {
                     Self::DEFAULT_BLACK,
                     Self::REGULAR_SIZE,
                 ),
-                Self::table_row(Self::A_MAL, "****", Self::DEFAULT_BLACK, Self::REGULAR_SIZE),
-                Self::table_row(Self::URL, "****", Self::DEFAULT_BLACK, Self::REGULAR_SIZE),
+                Self::table_row(
+                    Self::A_MAL,
+                    &malicious_attachments,
+                    Self::DEFAULT_BLACK,
+                    Self::REGULAR_SIZE,
+                ),
+                Self::table_row(Self::URL, &urls, Self::DEFAULT_BLACK, Self::REGULAR_SIZE),
                 Self::table_row(
                     Self::URL_MAL,
-                    "****",
+                    body_row,
                     Self::DEFAULT_BLACK,
                     Self::REGULAR_SIZE,
                 ),
@@ -159,6 +216,51 @@ impl NewDocx {
 
         docx = docx.add_paragraph(Paragraph::new().line_spacing(LineSpacing::new().after(200)));
 
+        if let Some(reported) = &email.reported {
+            docx = docx.add_paragraph(
+                Self::build_paragraph(Self::REPORTED_HEAD, Self::DARK_BLUE, Self::SIDE_HEAD_SIZE)
+                    .line_spacing(LineSpacing::new().after(200)),
+            );
+
+            docx = docx.add_table(
+                Table::new(vec![
+                    Self::table_row(
+                        Self::REPORT_FORMAT,
+                        &reported.format,
+                        Self::DEFAULT_BLACK,
+                        Self::REGULAR_SIZE,
+                    ),
+                    Self::table_row(
+                        Self::REPORTER,
+                        &self.indicator(&reported.reporter),
+                        Self::DEFAULT_BLACK,
+                        Self::REGULAR_SIZE,
+                    ),
+                    Self::table_row(
+                        Self::REPORT_TIME,
+                        &reported.report_time,
+                        Self::DEFAULT_BLACK,
+                        Self::REGULAR_SIZE,
+                    ),
+                    Self::table_row(
+                        Self::REPORT_SUBJECT,
+                        &self.indicator(&reported.report_subject),
+                        Self::DEFAULT_BLACK,
+                        Self::REGULAR_SIZE,
+                    ),
+                    Self::table_row(
+                        Self::REPORT_PART,
+                        &reported.inner_path,
+                        Self::DEFAULT_BLACK,
+                        Self::REGULAR_SIZE,
+                    ),
+                ])
+                .set_borders(TableBorders::new().clear_all()),
+            );
+
+            docx = docx.add_paragraph(Paragraph::new().line_spacing(LineSpacing::new().after(200)));
+        }
+
         docx = docx.add_paragraph(Self::build_paragraph(
             Self::REF,
             Self::DARK_BLUE,
@@ -196,49 +298,184 @@ impl NewDocx {
                     Self::DEFAULT_BLACK,
                     Self::REGULAR_SIZE,
                 ))
-                .add_run(Self::build_run(
-                    &from_address,
-                    Self::RED,
-                    Self::REGULAR_SIZE,
-                ))
+                .add_run(Self::build_run(&shown_from, Self::RED, Self::REGULAR_SIZE))
                 .add_run(Self::build_run(
                     Self::ANALYSIS_VEC[1],
                     Self::DEFAULT_BLACK,
                     Self::REGULAR_SIZE,
                 ))
                 .add_run(Self::build_run(
-                    &sender_domain,
+                    &shown_domain,
                     Self::RED,
                     Self::REGULAR_SIZE,
                 ))
                 .line_spacing(LineSpacing::new().after(200)),
         );
 
-        let att_count = if count == 0 {
-            String::from("no")
+        if email.headers_only {
+            docx = docx.add_paragraph(
+                Self::build_paragraph(Self::NO_BODY, Self::DEFAULT_BLACK, Self::REGULAR_SIZE)
+                    .line_spacing(LineSpacing::new().after(200)),
+            );
         } else {
-            count.to_string()
-        };
+            let att_count = if count == 0 {
+                String::from("no")
+            } else {
+                count.to_string()
+            };
+            let url_count = if email.urls.is_empty() {
+                String::from("no")
+            } else {
+                email.urls.len().to_string()
+            };
 
-        docx = docx.add_paragraph(
-            Paragraph::new()
-                .add_run(Self::build_run(
-                    Self::URL_ATTACHMENTS[0],
-                    Self::DEFAULT_BLACK,
-                    Self::REGULAR_SIZE,
-                ))
-                .add_run(Self::build_run(
-                    &att_count,
-                    Self::DEFAULT_BLACK,
-                    Self::REGULAR_SIZE,
-                ))
-                .add_run(Self::build_run(
-                    Self::URL_ATTACHMENTS[1],
-                    Self::DEFAULT_BLACK,
-                    Self::REGULAR_SIZE,
-                ))
+            docx = docx.add_paragraph(
+                Paragraph::new()
+                    .add_run(Self::build_run(
+                        Self::URL_ATTACHMENTS[0],
+                        Self::DEFAULT_BLACK,
+                        Self::REGULAR_SIZE,
+                    ))
+                    .add_run(Self::build_run(
+                        &url_count,
+                        Self::DEFAULT_BLACK,
+                        Self::REGULAR_SIZE,
+                    ))
+                    .add_run(Self::build_run(
+                        Self::URL_ATTACHMENTS[1],
+                        Self::DEFAULT_BLACK,
+                        Self::REGULAR_SIZE,
+                    ))
+                    .add_run(Self::build_run(
+                        &att_count,
+                        Self::DEFAULT_BLACK,
+                        Self::REGULAR_SIZE,
+                    ))
+                    .add_run(Self::build_run(
+                        Self::URL_ATTACHMENTS[2],
+                        Self::DEFAULT_BLACK,
+                        Self::REGULAR_SIZE,
+                    ))
+                    .line_spacing(LineSpacing::new().after(200)),
+            );
+        }
+
+        if !email.attachments.is_empty() {
+            docx = docx.add_paragraph(
+                Self::build_paragraph(
+                    Self::ATTACHMENT_TABLE_HEAD,
+                    Self::DARK_BLUE,
+                    Self::SIDE_HEAD_SIZE,
+                )
                 .line_spacing(LineSpacing::new().after(200)),
-        );
+            );
+
+            let rows = email
+                .attachments
+                .iter()
+                .enumerate()
+                .map(|(i, a)| self.attachment_row(i + 1, a))
+                .collect();
+            docx = docx.add_table(Self::grid_table(&Self::ATTACHMENT_COLUMNS, rows));
+
+            docx = docx.add_paragraph(Paragraph::new().line_spacing(LineSpacing::new().after(200)));
+
+            for (i, attachment) in email.attachments.iter().enumerate() {
+                if let Some(listing) = &attachment.archive {
+                    docx = self.archive_section(docx, i + 1, attachment, listing);
+                }
+                if let Some(report) = &attachment.macros {
+                    docx = self.macro_section(docx, i + 1, attachment, report);
+                }
+                if let Some(report) = &attachment.pdf {
+                    docx = self.pdf_section(docx, i + 1, attachment, report);
+                }
+                if let Some(report) = &attachment.harvest {
+                    docx = self.harvest_section(docx, i + 1, attachment, report);
+                }
+            }
+        }
+
+        if !email.link_mismatches.is_empty() {
+            docx = docx.add_paragraph(
+                Self::build_paragraph(Self::LINK_MISMATCH_HEAD, Self::RED, Self::REGULAR_SIZE)
+                    .line_spacing(LineSpacing::new().after(100)),
+            );
+            for mismatch in &email.link_mismatches {
+                docx = docx.add_paragraph(
+                    Self::build_paragraph(
+                        &format!("\t{}", self.describe_mismatch(mismatch)),
+                        Self::RED,
+                        Self::REGULAR_SIZE,
+                    )
+                    .line_spacing(LineSpacing::new().after(100)),
+                );
+            }
+            docx = docx.add_paragraph(Paragraph::new().line_spacing(LineSpacing::new().after(100)));
+        }
+
+        if !email.smuggling.is_empty() {
+            docx = docx.add_paragraph(
+                Self::build_paragraph(Self::SMUGGLING_HEAD, Self::RED, Self::REGULAR_SIZE)
+                    .line_spacing(LineSpacing::new().after(100)),
+            );
+            for found in &email.smuggling {
+                docx = docx.add_paragraph(
+                    Self::build_paragraph(
+                        &format!("\t{}", self.indicator(&Self::describe_smuggling(found))),
+                        Self::RED,
+                        Self::REGULAR_SIZE,
+                    )
+                    .line_spacing(LineSpacing::new().after(100)),
+                );
+            }
+            docx = docx.add_paragraph(Paragraph::new().line_spacing(LineSpacing::new().after(100)));
+        }
+
+        if !email.qr_codes.is_empty() {
+            docx = docx.add_paragraph(
+                Self::build_paragraph(Self::QR_HEAD, Self::RED, Self::REGULAR_SIZE)
+                    .line_spacing(LineSpacing::new().after(100)),
+            );
+            for qr in &email.qr_codes {
+                docx = docx.add_paragraph(
+                    Self::build_paragraph(
+                        &format!(
+                            "\t{}",
+                            self.indicator(&format!("{}: {}", qr.source, qr.text))
+                        ),
+                        Self::RED,
+                        Self::REGULAR_SIZE,
+                    )
+                    .line_spacing(LineSpacing::new().after(100)),
+                );
+            }
+            docx = docx.add_paragraph(Paragraph::new().line_spacing(LineSpacing::new().after(100)));
+        }
+
+        if !email.urls.is_empty() {
+            docx = docx.add_paragraph(
+                Self::build_paragraph(Self::URL_TABLE_HEAD, Self::DARK_BLUE, Self::SIDE_HEAD_SIZE)
+                    .line_spacing(LineSpacing::new().after(200)),
+            );
+
+            let rows = email
+                .urls
+                .iter()
+                .enumerate()
+                .map(|(i, u)| {
+                    vec![
+                        (i + 1).to_string(),
+                        self.indicator(&u.url),
+                        self.indicator(&Self::describe_unwrapped(u)),
+                        self.indicator(&u.sources.join(", ")),
+                    ]
+                })
+                .collect();
+            docx = docx.add_table(Self::grid_table(&Self::URL_COLUMNS, rows));
+
+            docx = docx.add_paragraph(Paragraph::new().line_spacing(LineSpacing::new().after(200)));
+        }
 
         docx = docx.add_paragraph(
             Self::build_paragraph(Self::DOMAIN_REP, Self::DEFAULT_BLACK, Self::REGULAR_SIZE)
@@ -254,7 +491,7 @@ impl NewDocx {
                     Self::REGULAR_SIZE,
                 ))
                 .add_run(Self::build_run(
-                    &sender_domain.to_ascii_lowercase(),
+                    &lookup_domain.to_ascii_lowercase(),
                     Self::DEFAULT_BLACK,
                     Self::REGULAR_SIZE,
                 ))
@@ -270,7 +507,7 @@ impl NewDocx {
                     Self::REGULAR_SIZE,
                 ))
                 .add_run(Self::build_run(
-                    &sender_domain.to_ascii_lowercase(),
+                    &lookup_domain.to_ascii_lowercase(),
                     Self::DEFAULT_BLACK,
                     Self::REGULAR_SIZE,
                 ))
@@ -286,17 +523,19 @@ impl NewDocx {
                     Self::REGULAR_SIZE,
                 ))
                 .add_run(Self::build_run(
-                    &sender_domain.to_ascii_lowercase(),
+                    &lookup_domain.to_ascii_lowercase(),
                     Self::DEFAULT_BLACK,
                     Self::REGULAR_SIZE,
                 ))
                 .line_spacing(LineSpacing::new().after(200)),
         );
 
-        docx = docx.add_paragraph(
-            Self::build_paragraph(Self::ANS_REPORT, Self::DARK_BLUE, Self::REGULAR_SIZE)
-                .line_spacing(LineSpacing::new().after(200)),
-        );
+        for statement in self.harvest_statements(email) {
+            docx = docx.add_paragraph(
+                Self::build_paragraph(&statement, Self::DARK_BLUE, Self::REGULAR_SIZE)
+                    .line_spacing(LineSpacing::new().after(200)),
+            );
+        }
 
         docx = docx.add_paragraph(
             Self::build_paragraph(Self::VERDICT_HEAD, Self::DARK_BLUE, Self::SIDE_HEAD_SIZE)
@@ -344,65 +583,47 @@ impl NewDocx {
                 .line_spacing(LineSpacing::new().after(200)),
         );
 
-        docx = docx.add_paragraph(
-            Paragraph::new()
-                .add_run(Self::build_run(
-                    Self::AUTH_RESULTS,
-                    Self::DARK_BLUE,
-                    Self::SIDE_HEAD_SIZE,
-                ))
-                .line_spacing(LineSpacing::new().after(200)),
-        );
-
-        docx = docx.add_paragraph(
-            Self::build_paragraph(&auth_result, Self::DEFAULT_BLACK, Self::REGULAR_SIZE)
-                .line_spacing(LineSpacing::new().after(200)),
-        );
-
-        docx = docx.add_paragraph(
-            Paragraph::new()
-                .add_run(Self::build_run(
-                    Self::RETURN_PATH,
-                    Self::DARK_BLUE,
-                    Self::SIDE_HEAD_SIZE,
-                ))
-                .line_spacing(LineSpacing::new().after(200)),
-        );
-
-        docx = docx.add_paragraph(
-            Self::build_paragraph(&return_path.trim(), Self::DEFAULT_BLACK, Self::REGULAR_SIZE)
-                .line_spacing(LineSpacing::new().after(200)),
-        );
+        for key in Self::HEADER_CHAIN {
+            let values: Vec<String> = headers
+                .get_all(key)
+                .iter()
+                .map(|v| self.indicator(v))
+                .collect();
+            docx = Self::header_section(docx, key, &values);
+        }
 
         docx = docx.add_paragraph(
             Paragraph::new()
                 .add_run(Self::build_run(
-                    Self::SPF,
+                    Self::MIME_TREE,
                     Self::DARK_BLUE,
                     Self::SIDE_HEAD_SIZE,
                 ))
                 .line_spacing(LineSpacing::new().after(200)),
         );
 
-        docx = docx.add_paragraph(
-            Self::build_paragraph(&spf, Self::DEFAULT_BLACK, Self::REGULAR_SIZE)
-                .line_spacing(LineSpacing::new().after(200)),
-        );
-
-        docx = docx.add_paragraph(
-            Paragraph::new()
-                .add_run(Self::build_run(
-                    Self::CONTENT_TYPE,
-                    Self::DARK_BLUE,
-                    Self::SIDE_HEAD_SIZE,
-                ))
-                .line_spacing(LineSpacing::new().after(200)),
-        );
+        if email.headers_only {
+            docx = docx.add_paragraph(
+                Self::build_paragraph(Self::NOT_AVAILABLE, Self::DEFAULT_BLACK, Self::REGULAR_SIZE)
+                    .line_spacing(LineSpacing::new().after(100)),
+            );
+        } else {
+            let path_depth = |path: &str| path.matches('.').count() + usize::from(!path.is_empty());
+            let root_depth = path_depth(&mime_tree.path);
+            for part in mime_tree.flatten() {
+                let depth = path_depth(&part.path) - root_depth;
+                docx = docx.add_paragraph(
+                    Self::build_paragraph(
+                        &format!("{}{}", "\t".repeat(depth), self.describe_part(part)),
+                        Self::DEFAULT_BLACK,
+                        Self::REGULAR_SIZE,
+                    )
+                    .line_spacing(LineSpacing::new().after(100)),
+                );
+            }
+        }
 
-        docx = docx.add_paragraph(
-            Self::build_paragraph(&h_content_type, Self::DEFAULT_BLACK, Self::REGULAR_SIZE)
-                .line_spacing(LineSpacing::new().after(200)),
-        );
+        docx = docx.add_paragraph(Paragraph::new().line_spacing(LineSpacing::new().after(200)));
 
         docx = docx.add_paragraph(
             Paragraph::new()
@@ -414,15 +635,35 @@ impl NewDocx {
                 .line_spacing(LineSpacing::new().after(200)),
         );
 
-        docx = docx.add_paragraph(
-            Paragraph::new()
-                .add_run(Self::build_run(
-                    &format!("{:?}", &b_headers),
+        if email.headers_only {
+            docx = docx.add_paragraph(
+                Self::build_paragraph(Self::NOT_AVAILABLE, Self::DEFAULT_BLACK, Self::REGULAR_SIZE)
+                    .line_spacing(LineSpacing::new().after(200)),
+            );
+        }
+
+        for part in mime_tree.flatten().into_iter().skip(1) {
+            docx = docx.add_paragraph(
+                Self::build_paragraph(
+                    &format!("[{}]", part.path),
                     Self::DEFAULT_BLACK,
                     Self::REGULAR_SIZE,
-                ))
-                .line_spacing(LineSpacing::new().after(200)),
-        );
+                )
+                .line_spacing(LineSpacing::new().after(50)),
+            );
+            for key in ["Content-Type", "Content-Disposition"] {
+                if let Some(value) = part.headers.get(key) {
+                    docx = docx.add_paragraph(
+                        Self::build_paragraph(
+                            &format!("\t{key}: {}", self.indicator(&Self::decoded_params(value))),
+                            Self::DEFAULT_BLACK,
+                            Self::REGULAR_SIZE,
+                        )
+                        .line_spacing(LineSpacing::new().after(50)),
+                    );
+                }
+            }
+        }
 
         docx = docx.page_margin(PageMargin {
             top: 1440,    // 1 inch (in twentieths of a point)
@@ -436,7 +677,69 @@ impl NewDocx {
             // ..PageMargin::default()
         });
 
-        return docx;
+        docx
+    }
+
+    /// Indicator text as it should appear in the report, defanged when the
+    /// report was asked to be.
+    fn indicator(&self, text: &str) -> String {
+        if self.defang {
+            Defang::text(text)
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// Like [`Self::indicator`] for a code listing, where only URLs and IP
+    /// addresses are defanged.
+    fn code(&self, text: &str) -> String {
+        if self.defang {
+            Defang::code(text)
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// Like [`Self::indicator`] for a name that is known to be a host, which
+    /// is defanged even when its top-level domain is not recognised.
+    fn host(&self, host: &str) -> String {
+        if self.defang {
+            Defang::host(host)
+        } else {
+            host.to_string()
+        }
+    }
+
+    /// Bordered table with a bold header row, used for the listings.
+    fn grid_table(header: &[&str], rows: Vec<Vec<String>>) -> Table {
+        let mut table_rows =
+            vec![TableRow::new(
+                header
+                    .iter()
+                    .map(|h| {
+                        TableCell::new().add_paragraph(Paragraph::new().add_run(
+                            Self::build_run(h, Self::DARK_BLUE, Self::REGULAR_SIZE).bold(),
+                        ))
+                    })
+                    .collect(),
+            )];
+        for row in rows {
+            table_rows.push(TableRow::new(
+                row.iter()
+                    .map(|c| {
+                        // Each line of a cell becomes its own paragraph.
+                        c.split('\n').fold(TableCell::new(), |cell, line| {
+                            cell.add_paragraph(Self::build_paragraph(
+                                line,
+                                Self::DEFAULT_BLACK,
+                                Self::SMALL_SIZE,
+                            ))
+                        })
+                    })
+                    .collect(),
+            ));
+        }
+        Table::new(table_rows)
     }
 
     fn table_row(side_head: &str, main_data: &str, color: &str, size: usize) -> TableRow {
@@ -452,10 +755,482 @@ impl NewDocx {
         ])
     }
 
-    fn get_values(key: &str, map: &HashMap<String, String>) -> String {
+    /// Writes a side heading followed by every occurrence of the header, in the
+    /// order they appear in the message.
+    fn header_section(mut docx: Docx, key: &str, values: &[String]) -> Docx {
+        docx = docx.add_paragraph(
+            Paragraph::new()
+                .add_run(Self::build_run(key, Self::DARK_BLUE, Self::SIDE_HEAD_SIZE))
+                .line_spacing(LineSpacing::new().after(200)),
+        );
+
+        if values.is_empty() {
+            return docx.add_paragraph(
+                Self::build_paragraph("NA", Self::DEFAULT_BLACK, Self::REGULAR_SIZE)
+                    .line_spacing(LineSpacing::new().after(200)),
+            );
+        }
+
+        for value in values {
+            docx = docx.add_paragraph(
+                Self::build_paragraph(value.trim(), Self::DEFAULT_BLACK, Self::REGULAR_SIZE)
+                    .line_spacing(LineSpacing::new().after(200)),
+            );
+        }
+        docx
+    }
+
+    /// One line summary of a MIME part: path, content type, disposition,
+    /// filename and decoded size.
+    fn describe_part(&self, part: &MimePart) -> String {
+        let path = if part.path.is_empty() {
+            "message"
+        } else {
+            &part.path
+        };
+        let mut line = format!("[{}] {}", path, part.content_type);
+        if let Some(disposition) = &part.disposition {
+            line.push_str(&format!(", {disposition}"));
+        }
+        if let Some(filename) = &part.filename {
+            line.push_str(&format!(", \"{}\"", self.indicator(filename)));
+        }
+        if part.children.is_empty() || part.size() > 0 {
+            line.push_str(&format!(", {} bytes", part.size()));
+        }
+        line
+    }
+
+    /// Rebuilds a parameterized header with every parameter decoded, so
+    /// encoded filenames are readable.
+    fn decoded_params(value: &str) -> String {
+        let (main, params) = Decode::params(value);
+        let mut line = main;
+        for (key, value) in params {
+            line.push_str(&format!("; {key}=\"{value}\""));
+        }
+        line
+    }
+
+    /// Entries of an archive attachment, listed under the inventory with the
+    /// number of its inventory row.
+    fn archive_section(
+        &self,
+        mut docx: Docx,
+        number: usize,
+        attachment: &Attachment,
+        listing: &ArchiveListing,
+    ) -> Docx {
+        let filename = self.indicator(attachment.filename.as_deref().unwrap_or("(no name)"));
+        docx = docx.add_paragraph(
+            Self::build_paragraph(
+                &format!(
+                    "{}{number}. {filename} ({}, {} {})",
+                    Self::ARCHIVE_HEAD,
+                    listing.format,
+                    listing.entries.len(),
+                    if listing.entries.len() == 1 {
+                        "entry"
+                    } else {
+                        "entries"
+                    }
+                ),
+                Self::DARK_BLUE,
+                Self::REGULAR_SIZE,
+            )
+            .line_spacing(LineSpacing::new().after(100)),
+        );
+
+        let rows = listing
+            .entries
+            .iter()
+            .map(|e| {
+                let hashes = match &e.hashes {
+                    Some(h) => format!("MD5: {}\nSHA-1: {}\nSHA-256: {}", h.md5, h.sha1, h.sha256),
+                    None => String::from("-"),
+                };
+                let yes_no = |flag: bool| String::from(if flag { "Yes" } else { "No" });
+                vec![
+                    format!("{}{}", "  ".repeat(e.depth - 1), self.indicator(&e.name)),
+                    format!("{} bytes", e.size),
+                    yes_no(e.encrypted),
+                    hashes,
+                    yes_no(e.suspicious),
+                ]
+            })
+            .collect();
+        docx = docx.add_table(Self::grid_table(&Self::ARCHIVE_COLUMNS, rows));
+
+        for warning in &listing.warnings {
+            docx = docx.add_paragraph(
+                Self::build_paragraph(&format!("\t{warning}"), Self::RED, Self::REGULAR_SIZE)
+                    .line_spacing(LineSpacing::new().after(50)),
+            );
+        }
+        docx.add_paragraph(Paragraph::new().line_spacing(LineSpacing::new().after(200)))
+    }
+
+    /// Macros of an Office attachment: what runs on open, the suspicious
+    /// calls and the module source, cut after `MAX_SOURCE_LINES` lines.
+    fn macro_section(
+        &self,
+        mut docx: Docx,
+        number: usize,
+        attachment: &Attachment,
+        report: &MacroReport,
+    ) -> Docx {
+        let filename = self.indicator(attachment.filename.as_deref().unwrap_or("(no name)"));
+        docx = docx.add_paragraph(
+            Self::build_paragraph(
+                &format!("{}{number}. {filename}", Self::MACRO_HEAD),
+                Self::DARK_BLUE,
+                Self::REGULAR_SIZE,
+            )
+            .line_spacing(LineSpacing::new().after(100)),
+        );
+
+        let findings = [
+            ("Auto-exec entry points", &report.auto_exec),
+            ("Suspicious calls", &report.suspicious),
+            ("XLM macro sheets", &report.xlm_sheets),
+            ("Warnings", &report.warnings),
+        ];
+        for (label, values) in findings.iter().filter(|(_, v)| !v.is_empty()) {
+            docx = docx.add_paragraph(
+                Self::build_paragraph(
+                    &format!("\t{label}: {}", values.join(", ")),
+                    Self::RED,
+                    Self::REGULAR_SIZE,
+                )
+                .line_spacing(LineSpacing::new().after(50)),
+            );
+        }
+
+        for module in &report.modules {
+            docx = docx.add_paragraph(
+                Self::build_paragraph(
+                    &format!("VBA module {}", module.name),
+                    Self::DEFAULT_BLACK,
+                    Self::REGULAR_SIZE,
+                )
+                .line_spacing(LineSpacing::new().before(100).after(50)),
+            );
+            let lines: Vec<&str> = module.source.lines().collect();
+            for line in lines.iter().take(Self::MAX_SOURCE_LINES) {
+                docx = docx.add_paragraph(
+                    Self::build_paragraph(&self.code(line), Self::DEFAULT_BLACK, Self::SMALL_SIZE)
+                        .line_spacing(LineSpacing::new().after(0)),
+                );
+            }
+            if lines.len() > Self::MAX_SOURCE_LINES {
+                docx = docx.add_paragraph(Self::build_paragraph(
+                    &format!("... {} more line(s)", lines.len() - Self::MAX_SOURCE_LINES),
+                    Self::RED,
+                    Self::SMALL_SIZE,
+                ));
+            }
+        }
+        docx.add_paragraph(Paragraph::new().line_spacing(LineSpacing::new().after(200)))
+    }
+
+    /// Active content and links of a PDF attachment, one line per finding.
+    fn pdf_section(
+        &self,
+        mut docx: Docx,
+        number: usize,
+        attachment: &Attachment,
+        report: &PdfReport,
+    ) -> Docx {
+        let filename = self.indicator(attachment.filename.as_deref().unwrap_or("(no name)"));
+        docx = docx.add_paragraph(
+            Self::build_paragraph(
+                &format!("{}{number}. {filename}", Self::PDF_HEAD),
+                Self::DARK_BLUE,
+                Self::REGULAR_SIZE,
+            )
+            .line_spacing(LineSpacing::new().after(100)),
+        );
+
+        let findings = [
+            ("JavaScript", &report.javascript),
+            ("Automatic action", &report.triggers),
+            ("Launch action", &report.launch),
+            ("Embedded file", &report.embedded_files),
+            ("Form", &report.forms),
+            ("Warning", &report.warnings),
+        ];
+        for (label, values) in findings {
+            for value in values {
+                let value = if label == "JavaScript" {
+                    self.code(value)
+                } else {
+                    self.indicator(value)
+                };
+                docx = docx.add_paragraph(
+                    Self::build_paragraph(
+                        &format!("\t{label}: {value}"),
+                        Self::RED,
+                        Self::REGULAR_SIZE,
+                    )
+                    .line_spacing(LineSpacing::new().after(50)),
+                );
+            }
+        }
+        for uri in &report.uris {
+            docx = docx.add_paragraph(
+                Self::build_paragraph(
+                    &format!("\tURI action: {}", self.indicator(uri)),
+                    Self::DEFAULT_BLACK,
+                    Self::REGULAR_SIZE,
+                )
+                .line_spacing(LineSpacing::new().after(50)),
+            );
+        }
+        if !report.has_findings() && report.uris.is_empty() && report.warnings.is_empty() {
+            docx = docx.add_paragraph(
+                Self::build_paragraph(Self::PDF_CLEAN, Self::DEFAULT_BLACK, Self::REGULAR_SIZE)
+                    .line_spacing(LineSpacing::new().after(50)),
+            );
+        }
+        docx.add_paragraph(Paragraph::new().line_spacing(LineSpacing::new().after(200)))
+    }
+
+    /// Evidence found in an HTML attachment, whether or not it adds up to a
+    /// credential harvesting page.
+    fn harvest_section(
+        &self,
+        mut docx: Docx,
+        number: usize,
+        attachment: &Attachment,
+        report: &HarvestReport,
+    ) -> Docx {
+        let filename = self.indicator(attachment.filename.as_deref().unwrap_or("(no name)"));
+        docx = docx.add_paragraph(
+            Self::build_paragraph(
+                &format!("{}{number}. {filename}", Self::HTML_HEAD),
+                Self::DARK_BLUE,
+                Self::REGULAR_SIZE,
+            )
+            .line_spacing(LineSpacing::new().after(100)),
+        );
+
+        let evidence = report.evidence();
+        if evidence.is_empty() {
+            docx = docx.add_paragraph(
+                Self::build_paragraph(Self::HTML_CLEAN, Self::DEFAULT_BLACK, Self::REGULAR_SIZE)
+                    .line_spacing(LineSpacing::new().after(50)),
+            );
+        }
+        let color = if report.is_harvesting() {
+            Self::RED
+        } else {
+            Self::DEFAULT_BLACK
+        };
+        for line in evidence {
+            docx = docx.add_paragraph(
+                Self::build_paragraph(
+                    &format!("\t{}", self.indicator(&line)),
+                    color,
+                    Self::REGULAR_SIZE,
+                )
+                .line_spacing(LineSpacing::new().after(50)),
+            );
+        }
+        docx.add_paragraph(Paragraph::new().line_spacing(LineSpacing::new().after(200)))
+    }
+
+    /// The credential harvesting sentence of the analysis, one for each
+    /// HTML attachment that has the evidence for it.
+    fn harvest_statements(&self, email: &ParsedEmail) -> Vec<String> {
+        email
+            .attachments
+            .iter()
+            .filter_map(|a| a.harvest.as_ref().map(|h| (a, h)))
+            .filter(|(_, h)| h.is_harvesting())
+            .map(|(a, h)| {
+                format!(
+                    "{}{}{}{}{}",
+                    Self::ANS_REPORT[0],
+                    self.indicator(a.filename.as_deref().unwrap_or("(no name)")),
+                    Self::ANS_REPORT[1],
+                    self.indicator(&h.evidence().join("; ")),
+                    Self::ANS_REPORT[2]
+                )
+            })
+            .collect()
+    }
+
+    /// Row 9 of the summary: the findings from the attachment checks, naming
+    /// the Office, PDF and HTML attachments with findings.
+    fn attachment_summary(&self, email: &ParsedEmail) -> String {
+        if email.headers_only {
+            return String::from(Self::NOT_AVAILABLE);
+        }
+        let count = email.attachments.len();
+
+        let tally = |check: &dyn Fn(&Attachment) -> bool| {
+            email.attachments.iter().filter(|a| check(a)).count()
+        };
+        let mut findings = Vec::new();
+        let mismatched = tally(&|a| !a.mismatches.is_empty());
+        if mismatched > 0 {
+            findings.push(format!("{mismatched} with a file type mismatch"));
+        }
+        let suspicious = tally(&|a| a.archive.as_ref().is_some_and(|l| l.has_suspicious()));
+        if suspicious > 0 {
+            findings.push(format!("{suspicious} archive(s) with suspicious entries"));
+        }
+        let encrypted = tally(&|a| a.archive.as_ref().is_some_and(|l| l.has_encrypted()));
+        if encrypted > 0 {
+            findings.push(format!("{encrypted} encrypted archive(s)"));
+        }
+        for attachment in &email.attachments {
+            if let Some(report) = attachment
+                .macros
+                .as_ref()
+                .filter(|r| !r.modules.is_empty() || r.has_findings())
+            {
+                findings.push(format!(
+                    "{} has {}",
+                    self.indicator(attachment.filename.as_deref().unwrap_or("(no name)")),
+                    report.describe()
+                ));
+            }
+            if attachment
+                .harvest
+                .as_ref()
+                .is_some_and(|h| h.is_harvesting())
+            {
+                findings.push(format!(
+                    "{} is a credential harvesting page",
+                    self.indicator(attachment.filename.as_deref().unwrap_or("(no name)"))
+                ));
+            }
+            if let Some(report) = attachment
+                .pdf
+                .as_ref()
+                .filter(|r| r.has_findings() || !r.uris.is_empty())
+            {
+                findings.push(format!(
+                    "{} has {}",
+                    self.indicator(attachment.filename.as_deref().unwrap_or("(no name)")),
+                    report.describe()
+                ));
+            }
+        }
+
+        for found in &email.smuggling {
+            findings.push(format!(
+                "HTML smuggling in part {} ({} payload(s) recovered)",
+                found.path,
+                found.payloads.len()
+            ));
+        }
+
+        if count == 0 && findings.is_empty() {
+            String::from("No")
+        } else if findings.is_empty() {
+            format!("No ({count} checked, no findings)")
+        } else {
+            format!("Suspicious ({count} checked: {})", findings.join("; "))
+        }
+    }
+
+    /// Inventory row of an attachment. Hashes are one per line in their cell.
+    fn attachment_row(&self, number: usize, attachment: &Attachment) -> Vec<String> {
+        let filename = self.indicator(attachment.filename.as_deref().unwrap_or("(no name)"));
+        let detected = attachment.detected_type.map_or("Unknown", |t| t.name);
+        let mismatch = if attachment.mismatches.is_empty() {
+            String::from("No")
+        } else {
+            format!("Yes: {}", attachment.mismatches.join("\n"))
+        };
+        vec![
+            number.to_string(),
+            if attachment.recovered {
+                format!("[{}] {filename} (recovered)", attachment.path)
+            } else {
+                format!("[{}] {filename}", attachment.path)
+            },
+            attachment.content_type.to_owned(),
+            detected.to_string(),
+            format!("{} bytes", attachment.size),
+            match &attachment.hashes {
+                Some(h) => format!("MD5: {}\nSHA-1: {}\nSHA-256: {}", h.md5, h.sha1, h.sha256),
+                None => String::from("-"),
+            },
+            mismatch,
+        ]
+    }
+
+    /// Where the page is, how it builds the file and what was recovered.
+    fn describe_smuggling(found: &Smuggling) -> String {
+        let mut line = format!("Part {}: {}", found.path, found.techniques.join(", "));
+        if found.payloads.is_empty() {
+            line.push_str("; the payload could not be recovered");
+        }
+        for payload in &found.payloads {
+            let detected = FileType::detect(&payload.data).map_or("unknown type", |t| t.name);
+            line.push_str(&format!(
+                "; recovered {} ({} bytes, {detected}, SHA-256 {})",
+                payload.filename.as_deref().unwrap_or("(no name)"),
+                payload.data.len(),
+                Hashes::of(&payload.data).sha256
+            ));
+        }
+        line
+    }
+
+    /// Each unwrapped layer with the gateway or redirector that wrapped it,
+    /// `-` for links that lead straight to their target.
+    fn describe_unwrapped(url: &ExtractedUrl) -> String {
+        if url.unwrapped.is_empty() {
+            return String::from("-");
+        }
+        url.unwrapped
+            .iter()
+            .map(|u| format!("{} ({})", u.url, u.via))
+            .collect::<Vec<_>>()
+            .join(" -> ")
+    }
+
+    fn describe_mismatch(&self, mismatch: &LinkMismatch) -> String {
+        let target = if mismatch.destination == mismatch.href {
+            mismatch.href.to_owned()
+        } else {
+            format!("{} through {}", mismatch.destination, mismatch.href)
+        };
+        let part = if mismatch.path.is_empty() {
+            "message"
+        } else {
+            &mismatch.path
+        };
+        format!(
+            "[{part}] Link text \"{}\" ({}) points to {} ({})",
+            self.indicator(&mismatch.text),
+            self.host(&mismatch.text_host),
+            self.indicator(&target),
+            self.host(&mismatch.href_host)
+        )
+    }
+
+    /// Formats a parsed address list, falling back to the header text when
+    /// nothing could be parsed from it.
+    fn address_list(addresses: &[Address], raw: Option<&str>) -> String {
+        if addresses.is_empty() {
+            return raw.unwrap_or("NA").to_string();
+        }
+        addresses
+            .iter()
+            .map(|a| a.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn get_values(key: &str, map: &HeaderMap) -> String {
         match map.get(key) {
             Some(v) => v.to_owned(),
-            None => format!("NA"),
+            None => String::from("NA"),
         }
     }
 
@@ -482,6 +1257,9 @@ impl NewDocx {
 }
 
 impl NewDocx {
+    /// Output path that writes the report to standard output.
+    pub const STDOUT: &'static str = "-";
+
     const DARK_BLUE: &'static str = "#1D076D";
     const RED: &'static str = "#FF0000";
     const DEFAULT_BLACK: &'static str = "#000000";
@@ -489,6 +1267,7 @@ impl NewDocx {
     const HEADING_SIZE: usize = 36;
     const SIDE_HEAD_SIZE: usize = 28;
     const REGULAR_SIZE: usize = 22;
+    const SMALL_SIZE: usize = 18;
 
     const HEADING: &'static str = "INCIDENT";
     const SE_LINE: &'static str = "Please find the following initial analysis details.";
@@ -506,6 +1285,16 @@ impl NewDocx {
     const URL: &'static str = "10. URL(S)";
     const URL_MAL: &'static str = "11. URL (Malicious)";
 
+    const REPORTED_HEAD: &'static str = "Reported Message";
+    const REPORT_FORMAT: &'static str = "Report Format";
+    const REPORTER: &'static str = "Reported By";
+    const REPORT_TIME: &'static str = "Reported On";
+    const REPORT_SUBJECT: &'static str = "Report Subject";
+    const REPORT_PART: &'static str = "Original Message Part";
+
+    const NOT_AVAILABLE: &'static str = "Not available (headers only)";
+    const NO_BODY: &'static str = "Only the message headers were provided, the URL(s) and Attachment(s) in the email body could not be analyzed.";
+
     const REF: &'static str = "Ref: ";
     const DOMAIN_ANALYSIS_HEAD: &'static str = "Domain Analysis";
     const ANALYSIS_HEAD: &'static str = "Analysis";
@@ -514,11 +1303,50 @@ impl NewDocx {
         " which was detected as a ***-suspicious mail. As per the initial analysis we gathered that the mail came from ",
     ];
 
-    const URL_ATTACHMENTS: [&'static str; 2] = [
-        "We also observed that there are *** URL(s) and ",
+    const URL_ATTACHMENTS: [&'static str; 3] = [
+        "We also observed that there are ",
+        " URL(s) and ",
         " Attachment(s) in this email body.",
     ];
 
+    const LINK_MISMATCH_HEAD: &'static str =
+        "The following link(s) display one address but lead to another:";
+
+    const SMUGGLING_HEAD: &'static str =
+        "The following HTML part(s) build a file in the browser (HTML smuggling):";
+
+    const QR_HEAD: &'static str =
+        "The following QR code(s) were decoded from images in this email:";
+
+    const ATTACHMENT_TABLE_HEAD: &'static str = "Attachment(s)";
+    const ATTACHMENT_COLUMNS: [&'static str; 7] = [
+        "#",
+        "Filename",
+        "Declared Type",
+        "Detected Type",
+        "Size",
+        "Hashes",
+        "Type Mismatch",
+    ];
+
+    const ARCHIVE_HEAD: &'static str = "Archive contents of attachment ";
+    const ARCHIVE_COLUMNS: [&'static str; 5] =
+        ["Entry", "Size", "Encrypted", "Hashes", "Suspicious"];
+
+    const MACRO_HEAD: &'static str = "Macros in attachment ";
+    const MAX_SOURCE_LINES: usize = 200;
+
+    const PDF_HEAD: &'static str = "PDF analysis of attachment ";
+    const PDF_CLEAN: &'static str =
+        "\tNo JavaScript, automatic actions, Launch actions, embedded files, forms or links.";
+
+    const HTML_HEAD: &'static str = "HTML analysis of attachment ";
+    const HTML_CLEAN: &'static str =
+        "\tNo password fields, remote form actions, script posts, obfuscation or pre-filled addresses.";
+
+    const URL_TABLE_HEAD: &'static str = "URL(s)";
+    const URL_COLUMNS: [&'static str; 4] = ["#", "URL", "Unwrapped Chain", "Found In"];
+
     const DOMAIN_REP: &'static str =
         "The Domain is clean as per virus total, Kaspersky and URL void.";
     const DOMAIN_REP_RES: [&'static str; 3] = [
@@ -535,11 +1363,24 @@ impl NewDocx {
     const SCREEN_SHOT: &'static str = "Screenshots:";
 
     const HEADERS: &'static str = "Mail - Headers";
-    const RETURN_PATH: &'static str = "Return-Path";
-    const CONTENT_TYPE: &'static str = "Content-Type";
-    const SPF: &'static str = "Received-Spf";
-    const AUTH_RESULTS: &'static str = "Authentication-Results";
+    const HEADER_CHAIN: [&'static str; 10] = [
+        "Authentication-Results",
+        "Return-Path",
+        "Received-SPF",
+        "Received",
+        "DKIM-Signature",
+        "ARC-Seal",
+        "ARC-Message-Signature",
+        "ARC-Authentication-Results",
+        "X-Originating-IP",
+        "Content-Type",
+    ];
+    const MIME_TREE: &'static str = "MIME Structure";
     const B_CTYPE: &'static str = "Body Content-Type";
 
-    const ANS_REPORT: &'static str = "As per the analysis we observed that, there is ******** Attached file is an html document and is trying to get the credentials of the user. Intention of the mail is credential harvesting.";
+    const ANS_REPORT: [&'static str; 3] = [
+        "As per the analysis we observed that the attached file ",
+        " is an html document and is trying to get the credentials of the user (",
+        "). Intention of the mail is credential harvesting.",
+    ];
 }
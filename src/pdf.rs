@@ -0,0 +1,350 @@
+use crate::filetype::FileType;
+use lopdf::{Dictionary, Document, Object};
+use regex::bytes::Regex;
+use std::sync::LazyLock;
+
+/// Active content and links found in a PDF attachment. Credential phishing
+/// PDFs often carry nothing but one URI action behind a "View Document"
+/// button, so the URIs are listed even when nothing else is found.
+#[derive(Debug, Clone, Default)]
+pub struct PdfReport {
+    /// Where each script is attached, with the start of its code.
+    pub javascript: Vec<String>,
+    /// OpenAction and additional actions (AA) that run without a click.
+    pub triggers: Vec<String>,
+    /// Programs or files started by Launch actions.
+    pub launch: Vec<String>,
+    /// Names of embedded files.
+    pub embedded_files: Vec<String>,
+    /// Interactive forms and where they submit to.
+    pub forms: Vec<String>,
+    /// Targets of URI actions, in the order they appear.
+    pub uris: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+// A name as written in the file, `#xx` escapes included, used when the
+// document can't be parsed.
+static RAW_NAME: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"/([A-Za-z0-9#]+)").unwrap());
+
+static RAW_URI: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"/URI\s*\(((?:[^()\\]|\\.)*)\)").unwrap());
+
+impl PdfReport {
+    /// Analyses `data` when it is a PDF, `None` otherwise.
+    pub fn inspect(data: &[u8], detected: Option<&FileType>) -> Option<PdfReport> {
+        if detected != Some(&FileType::PDF) {
+            return None;
+        }
+        let mut report = PdfReport::default();
+        match Document::load_mem(data) {
+            Ok(doc) => report.walk(&doc),
+            Err(err) => {
+                report.warnings.push(format!(
+                    "PDF not parsed ({err}), findings come from a raw keyword scan"
+                ));
+                report.scan_raw(data);
+            }
+        }
+        Some(report)
+    }
+
+    /// Whether the PDF can do anything beyond showing pages and links.
+    pub fn has_findings(&self) -> bool {
+        !self.javascript.is_empty()
+            || !self.triggers.is_empty()
+            || !self.launch.is_empty()
+            || !self.embedded_files.is_empty()
+            || !self.forms.is_empty()
+    }
+
+    /// Short description of the findings for the report summary.
+    pub fn describe(&self) -> String {
+        let counts = [
+            (self.javascript.len(), "JavaScript"),
+            (self.triggers.len(), "automatic action(s)"),
+            (self.launch.len(), "Launch action(s)"),
+            (self.embedded_files.len(), "embedded file(s)"),
+            (self.forms.len(), "form finding(s)"),
+            (self.uris.len(), "URI action(s)"),
+        ];
+        counts
+            .iter()
+            .filter(|(count, _)| *count > 0)
+            .map(|(count, label)| format!("{count} {label}"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn walk(&mut self, doc: &Document) {
+        if doc.is_encrypted() {
+            self.warnings.push(String::from(
+                "PDF is encrypted with a password, strings could not be read",
+            ));
+        }
+
+        if let Ok(catalog) = doc.catalog() {
+            if let Ok(action) = catalog.get_deref(b"OpenAction", doc) {
+                // A bare destination only opens a page.
+                if let Some(kind) = Self::action_kind(doc, action) {
+                    self.triggers.push(format!("OpenAction runs {kind}"));
+                }
+            }
+            if let Ok(form) = catalog
+                .get_deref(b"AcroForm", doc)
+                .and_then(Object::as_dict)
+            {
+                let fields = form
+                    .get_deref(b"Fields", doc)
+                    .and_then(Object::as_array)
+                    .map_or(0, Vec::len);
+                self.forms.push(format!("AcroForm with {fields} field(s)"));
+                if form.has(b"XFA") {
+                    self.forms.push(String::from("XFA form"));
+                }
+            }
+        }
+
+        for (&(number, _), object) in &doc.objects {
+            self.visit(doc, object, number, 0);
+        }
+    }
+
+    /// Checks every dictionary in an object, inline dictionaries included,
+    /// since actions are usually written inside the annotation that uses them.
+    fn visit(&mut self, doc: &Document, object: &Object, number: u32, depth: usize) {
+        if depth > Self::MAX_NESTING {
+            return;
+        }
+        let dict = match object {
+            Object::Dictionary(dict) => dict,
+            Object::Stream(stream) => &stream.dict,
+            Object::Array(items) => {
+                for item in items {
+                    self.visit(doc, item, number, depth + 1);
+                }
+                return;
+            }
+            _ => return,
+        };
+
+        self.check(doc, dict, number);
+        for (_, value) in dict.iter() {
+            self.visit(doc, value, number, depth + 1);
+        }
+    }
+
+    fn check(&mut self, doc: &Document, dict: &Dictionary, number: u32) {
+        if let Ok(code) = dict.get_deref(b"JS", doc) {
+            self.javascript.push(format!(
+                "object {number}: {}",
+                Self::snippet(&Self::code(code))
+            ));
+        }
+
+        match Self::name(dict, b"S") {
+            Some("URI") => {
+                if let Ok(uri) = dict.get_deref(b"URI", doc).and_then(Object::as_str) {
+                    let uri = Self::text(uri);
+                    if !self.uris.contains(&uri) {
+                        self.uris.push(uri);
+                    }
+                }
+            }
+            Some("Launch") => {
+                let target =
+                    Self::launch_target(doc, dict).unwrap_or_else(|| String::from("(no target)"));
+                self.launch.push(format!("object {number}: {target}"));
+            }
+            Some("SubmitForm") => {
+                let target =
+                    Self::file_name(doc, dict).unwrap_or_else(|| String::from("(no target)"));
+                self.forms.push(format!("form submits to {target}"));
+                if !self.uris.contains(&target) {
+                    self.uris.push(target);
+                }
+            }
+            _ => {}
+        }
+
+        if let Ok(actions) = dict.get_deref(b"AA", doc).and_then(Object::as_dict) {
+            let owner = Self::name(dict, b"Type")
+                .or_else(|| Self::name(dict, b"Subtype"))
+                .unwrap_or("object");
+            for (event, action) in actions.iter() {
+                let kind = doc
+                    .dereference(action)
+                    .ok()
+                    .and_then(|(_, action)| Self::action_kind(doc, action))
+                    .unwrap_or_else(|| String::from("an action"));
+                self.triggers.push(format!(
+                    "{owner} {number} event {} runs {kind}",
+                    String::from_utf8_lossy(event)
+                ));
+            }
+        }
+
+        if Self::name(dict, b"Type") == Some("Filespec") && dict.has(b"EF") {
+            let name = Self::file_name(doc, dict).unwrap_or_else(|| String::from("(no name)"));
+            self.embedded_files.push(name);
+        }
+    }
+
+    /// The action type of an action dictionary, `None` for destinations and
+    /// plain GoTo actions.
+    fn action_kind(doc: &Document, action: &Object) -> Option<String> {
+        let dict = action.as_dict().ok()?;
+        let kind = Self::name(dict, b"S")?;
+        match kind {
+            "GoTo" => None,
+            "URI" => {
+                let uri = dict.get_deref(b"URI", doc).and_then(Object::as_str).ok()?;
+                Some(format!("URI {}", Self::text(uri)))
+            }
+            kind => Some(kind.to_string()),
+        }
+    }
+
+    /// The program of a Launch action with its parameters, from the
+    /// platform-neutral `F` entry or the Windows-specific `Win` dictionary.
+    fn launch_target(doc: &Document, dict: &Dictionary) -> Option<String> {
+        if let Some(file) = Self::file_name(doc, dict) {
+            return Some(file);
+        }
+        let win = dict.get_deref(b"Win", doc).and_then(Object::as_dict).ok()?;
+        let file = Self::file_name(doc, win)?;
+        match win.get(b"P").and_then(Object::as_str) {
+            Ok(params) => Some(format!("{file} {}", Self::text(params))),
+            Err(_) => Some(file),
+        }
+    }
+
+    /// A file specification given as a string or a dictionary, preferring
+    /// the Unicode name.
+    fn file_name(doc: &Document, dict: &Dictionary) -> Option<String> {
+        for key in [b"UF".as_slice(), b"F"] {
+            match dict.get_deref(key, doc) {
+                Ok(Object::String(name, _)) => return Some(Self::text(name)),
+                Ok(Object::Dictionary(spec)) => return Self::file_name(doc, spec),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    fn name<'a>(dict: &'a Dictionary, key: &[u8]) -> Option<&'a str> {
+        dict.get(key)
+            .and_then(Object::as_name)
+            .ok()
+            .and_then(|n| std::str::from_utf8(n).ok())
+    }
+
+    fn code(object: &Object) -> String {
+        match object {
+            Object::String(code, _) => Self::text(code),
+            Object::Stream(stream) => stream
+                .decompressed_content_with_limit(Self::MAX_STREAM_SIZE)
+                .map(|code| Self::text(&code))
+                .unwrap_or_else(|_| String::from_utf8_lossy(&stream.content).into_owned()),
+            _ => String::new(),
+        }
+    }
+
+    /// PDF text strings are UTF-16BE with a byte order mark, or
+    /// PDFDocEncoding, which matches Latin-1 for everything that matters here.
+    fn text(bytes: &[u8]) -> String {
+        match bytes.strip_prefix(&[0xFE, 0xFF]) {
+            Some(utf16) => {
+                let units: Vec<u16> = utf16
+                    .chunks_exact(2)
+                    .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                    .collect();
+                String::from_utf16_lossy(&units)
+            }
+            None => bytes.iter().map(|&b| char::from(b)).collect(),
+        }
+    }
+
+    fn snippet(code: &str) -> String {
+        let code = code.split_whitespace().collect::<Vec<_>>().join(" ");
+        match code.char_indices().nth(Self::SNIPPET_LENGTH) {
+            Some((end, _)) => format!("{}...", &code[..end]),
+            None => code,
+        }
+    }
+
+    /// Fallback for files the parser rejects: counts the keywords in the
+    /// raw bytes, undoing `#xx` escapes in names, and picks out plain URIs.
+    fn scan_raw(&mut self, data: &[u8]) {
+        let mut counts = [0usize; 7];
+        for cap in RAW_NAME.captures_iter(data) {
+            let name = Self::unescape_name(&cap[1]);
+            if let Some(i) = Self::RAW_KEYWORDS.iter().position(|k| *k == name) {
+                counts[i] += 1;
+            }
+        }
+        let seen = |i: usize| counts[i] > 0;
+        let times = |i: usize| format!("/{} seen {} time(s)", Self::RAW_KEYWORDS[i], counts[i]);
+
+        for i in [0, 1].into_iter().filter(|i| seen(*i)) {
+            self.javascript.push(times(i));
+        }
+        for i in [2, 3].into_iter().filter(|i| seen(*i)) {
+            self.triggers.push(times(i));
+        }
+        if seen(4) {
+            self.launch.push(times(4));
+        }
+        if seen(5) {
+            self.embedded_files.push(times(5));
+        }
+        if seen(6) {
+            self.forms.push(times(6));
+        }
+
+        for cap in RAW_URI.captures_iter(data) {
+            let uri = Self::text(&cap[1]).replace("\\", "");
+            if !self.uris.contains(&uri) {
+                self.uris.push(uri);
+            }
+        }
+    }
+
+    fn unescape_name(raw: &[u8]) -> String {
+        let mut out = Vec::with_capacity(raw.len());
+        let mut i = 0;
+        while i < raw.len() {
+            let hex = raw
+                .get(i + 1..i + 3)
+                .and_then(|h| std::str::from_utf8(h).ok())
+                .and_then(|h| u8::from_str_radix(h, 16).ok());
+            match (raw[i], hex) {
+                (b'#', Some(byte)) => {
+                    out.push(byte);
+                    i += 3;
+                }
+                (byte, _) => {
+                    out.push(byte);
+                    i += 1;
+                }
+            }
+        }
+        String::from_utf8_lossy(&out).into_owned()
+    }
+}
+
+impl PdfReport {
+    const MAX_NESTING: usize = 16;
+    const MAX_STREAM_SIZE: usize = 10 * 1024 * 1024;
+    const SNIPPET_LENGTH: usize = 120;
+
+    const RAW_KEYWORDS: [&'static str; 7] = [
+        "JavaScript",
+        "JS",
+        "OpenAction",
+        "AA",
+        "Launch",
+        "EmbeddedFile",
+        "AcroForm",
+    ];
+}
@@ -0,0 +1,282 @@
+use crate::{decode::LENIENT_BASE64, email::Body, filetype::FileType, mime::MimePart};
+use base64::Engine;
+use image::{imageops, GrayImage};
+use lopdf::{Document, Object, Stream};
+use regex::Regex;
+use rqrr::PreparedImage;
+use std::{
+    io::{Cursor, Read},
+    sync::LazyLock,
+};
+use zip::ZipArchive;
+
+/// Text decoded from a QR code in one of the images of a message. Quishing
+/// mails carry the lure URL only in such an image.
+#[derive(Debug, Clone)]
+pub struct QrCode {
+    pub text: String,
+    /// Where the image was found, such as `QR code in image part 1.2 (cid:logo)`.
+    pub source: String,
+}
+
+static DATA_IMAGE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)data:image/[a-z0-9.+-]+;base64,([A-Za-z0-9+/=\s]+)").unwrap()
+});
+
+impl QrCode {
+    /// Decodes the QR codes in every image part, in the `data:` images of
+    /// HTML bodies and in the images inside PDF and OOXML attachments.
+    pub fn find(mime: &MimePart, bodies: &[Body]) -> Vec<QrCode> {
+        let mut found = Vec::new();
+        for part in mime.flatten().into_iter().filter(|p| !p.body.is_empty()) {
+            let detected = FileType::detect(&part.body);
+            let label = Self::label(part);
+            if detected.is_some_and(|t| Self::IMAGE_TYPES.contains(&t)) {
+                let source = format!("QR code in image part {} ({label})", part.path);
+                Self::add(&mut found, Self::decode_image(&part.body), &source);
+            } else if detected == Some(&FileType::PDF) {
+                let source = format!("QR code in PDF part {} ({label})", part.path);
+                for image in Self::pdf_images(&part.body) {
+                    Self::add(&mut found, Self::decode_gray(image), &source);
+                }
+            } else if detected.is_some_and(|t| FileType::OOXML.contains(&t)) {
+                for (name, data) in Self::office_images(&part.body) {
+                    let source = format!("QR code in {name} of part {} ({label})", part.path);
+                    Self::add(&mut found, Self::decode_image(&data), &source);
+                }
+            }
+        }
+
+        for body in bodies.iter().filter(|b| b.content_type == "text/html") {
+            let source = format!("QR code in data: URI image in part {}", body.path);
+            for cap in DATA_IMAGE.captures_iter(&body.text) {
+                let encoded: String = cap[1].chars().filter(|c| !c.is_whitespace()).collect();
+                if let Ok(data) = LENIENT_BASE64.decode(encoded) {
+                    Self::add(&mut found, Self::decode_image(&data), &source);
+                }
+            }
+        }
+        found
+    }
+
+    /// Decodes every QR code in a PNG, JPEG, GIF, BMP or WebP image.
+    pub fn decode_image(data: &[u8]) -> Vec<String> {
+        match image::load_from_memory(data) {
+            Ok(image) => Self::decode_gray(image.to_luma8()),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Codes drawn with one pixel per module are too small for the detector,
+    /// small images get a second try scaled up.
+    fn decode_gray(image: GrayImage) -> Vec<String> {
+        let (width, height) = image.dimensions();
+        if u64::from(width) * u64::from(height) > Self::MAX_PIXELS {
+            return Vec::new();
+        }
+        let texts = Self::detect(&image);
+        if !texts.is_empty() || width.max(height) >= Self::SMALL_IMAGE {
+            return texts;
+        }
+        let scaled = imageops::resize(
+            &image,
+            width * Self::SCALE,
+            height * Self::SCALE,
+            imageops::FilterType::Nearest,
+        );
+        Self::detect(&scaled)
+    }
+
+    fn detect(image: &GrayImage) -> Vec<String> {
+        let mut prepared = PreparedImage::prepare_from_greyscale(
+            image.width() as usize,
+            image.height() as usize,
+            |x, y| image.get_pixel(x as u32, y as u32).0[0],
+        );
+        prepared
+            .detect_grids()
+            .into_iter()
+            .filter_map(|grid| grid.decode().ok().map(|(_, text)| text))
+            .collect()
+    }
+
+    /// Image XObjects of a PDF: JPEG streams as they are, and raw gray or
+    /// RGB pixels at 1 or 8 bits per component.
+    fn pdf_images(data: &[u8]) -> Vec<GrayImage> {
+        let Ok(doc) = Document::load_mem(data) else {
+            return Vec::new();
+        };
+        doc.objects
+            .values()
+            .filter_map(|object| object.as_stream().ok())
+            .filter(|stream| {
+                stream
+                    .dict
+                    .get(b"Subtype")
+                    .and_then(Object::as_name)
+                    .is_ok_and(|n| n == b"Image")
+            })
+            .filter_map(|stream| Self::pdf_image(&doc, stream))
+            .collect()
+    }
+
+    fn pdf_image(doc: &Document, stream: &Stream) -> Option<GrayImage> {
+        let filters = stream.filters().unwrap_or_default();
+        if filters == [b"DCTDecode".as_slice()] {
+            return image::load_from_memory(&stream.content)
+                .ok()
+                .map(|image| image.to_luma8());
+        }
+
+        let number = |key: &[u8]| {
+            stream
+                .dict
+                .get(key)
+                .and_then(Object::as_i64)
+                .ok()
+                .and_then(|n| u32::try_from(n).ok())
+        };
+        let width = number(b"Width")?;
+        let height = number(b"Height")?;
+        let bits = number(b"BitsPerComponent").unwrap_or(1);
+        let mask = stream
+            .dict
+            .get(b"ImageMask")
+            .and_then(Object::as_bool)
+            .unwrap_or(false);
+        let components = if mask {
+            1
+        } else {
+            Self::pdf_components(doc, stream)?
+        };
+        if u64::from(width) * u64::from(height) > Self::MAX_PIXELS {
+            return None;
+        }
+
+        let pixels = stream
+            .decompressed_content_with_limit(Self::MAX_PIXELS as usize * 4)
+            .ok()?;
+        let row = match bits {
+            1 if components == 1 => (width as usize).div_ceil(8),
+            8 => width as usize * components,
+            _ => return None,
+        };
+        if pixels.len() < row * height as usize {
+            return None;
+        }
+
+        Some(GrayImage::from_fn(width, height, |x, y| {
+            let (x, line) = (x as usize, &pixels[y as usize * row..]);
+            let value = match bits {
+                1 => (line[x / 8] >> (7 - x % 8) & 1) * 255,
+                _ if components == 1 => line[x],
+                _ => {
+                    let rgb = &line[x * 3..x * 3 + 3];
+                    ((u16::from(rgb[0]) + u16::from(rgb[1]) + u16::from(rgb[2])) / 3) as u8
+                }
+            };
+            image::Luma([value])
+        }))
+    }
+
+    /// Components per pixel of the gray and RGB color spaces, including
+    /// ICC-based ones, `None` for anything else.
+    fn pdf_components(doc: &Document, stream: &Stream) -> Option<usize> {
+        let space = stream.dict.get_deref(b"ColorSpace", doc).ok()?;
+        let name = match space {
+            Object::Name(name) => name.as_slice(),
+            Object::Array(items) => {
+                let family = items.first()?.as_name().ok()?;
+                if family != b"ICCBased" {
+                    return None;
+                }
+                let (_, profile) = doc.dereference(items.get(1)?).ok()?;
+                let n = profile
+                    .as_stream()
+                    .ok()?
+                    .dict
+                    .get(b"N")
+                    .and_then(Object::as_i64);
+                return match n.ok()? {
+                    1 => Some(1),
+                    3 => Some(3),
+                    _ => None,
+                };
+            }
+            _ => return None,
+        };
+        match name {
+            b"DeviceGray" | b"CalGray" => Some(1),
+            b"DeviceRGB" | b"CalRGB" => Some(3),
+            _ => None,
+        }
+    }
+
+    /// Pictures stored in the media folders of a Word, Excel or PowerPoint
+    /// file, as `(entry name, content)`.
+    fn office_images(data: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let Ok(mut zip) = ZipArchive::new(Cursor::new(data)) else {
+            return Vec::new();
+        };
+        let names: Vec<String> = zip
+            .file_names()
+            .filter(|n| n.contains("/media/"))
+            .map(str::to_string)
+            .collect();
+
+        let mut images = Vec::new();
+        for name in names {
+            let mut content = Vec::new();
+            let read = zip.by_name(&name).is_ok_and(|file| {
+                file.take(Self::MAX_IMAGE_SIZE)
+                    .read_to_end(&mut content)
+                    .is_ok()
+            });
+            let image = FileType::detect(&content).is_some_and(|t| Self::IMAGE_TYPES.contains(&t));
+            if read && image {
+                images.push((name, content));
+            }
+        }
+        images
+    }
+
+    /// The filename of a part, or its Content-ID for inline images.
+    fn label(part: &MimePart) -> String {
+        if let Some(filename) = &part.filename {
+            return filename.to_owned();
+        }
+        match part.headers.get("Content-ID") {
+            Some(id) => format!(
+                "cid:{}",
+                id.trim().trim_start_matches('<').trim_end_matches('>')
+            ),
+            None => part.content_type.to_owned(),
+        }
+    }
+
+    fn add(found: &mut Vec<QrCode>, texts: Vec<String>, source: &str) {
+        for text in texts {
+            if !found.iter().any(|q| q.text == text && q.source == source) {
+                found.push(QrCode {
+                    text,
+                    source: source.to_string(),
+                });
+            }
+        }
+    }
+}
+
+impl QrCode {
+    const MAX_PIXELS: u64 = 40_000_000;
+    const MAX_IMAGE_SIZE: u64 = 20 * 1024 * 1024;
+    const SMALL_IMAGE: u32 = 400;
+    const SCALE: u32 = 4;
+
+    const IMAGE_TYPES: [&'static FileType; 5] = [
+        &FileType::PNG,
+        &FileType::JPEG,
+        &FileType::GIF,
+        &FileType::BMP,
+        &FileType::WEBP,
+    ];
+}
@@ -0,0 +1,250 @@
+use crate::{
+    decode::{Decode, LENIENT_BASE64, LENIENT_BASE64_URL},
+    error::{Error, Result},
+    urls::UrlExtractor,
+};
+use base64::Engine;
+use std::{fs, path::Path};
+use url::Url;
+
+/// How a redirector stores its target in the query parameter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamEncoding {
+    /// Percent-encoded URL.
+    Plain,
+    /// Base64 of the URL after skipping the given number of leading
+    /// characters, Bing prefixes its value with `a1`.
+    Base64(usize),
+}
+
+/// One known open redirector: links to `host` (or a subdomain of it) whose
+/// path starts with `path` carry the real target in one of `params`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redirector {
+    pub name: String,
+    pub host: String,
+    pub path: String,
+    pub params: Vec<String>,
+    pub encoding: ParamEncoding,
+}
+
+/// The redirectors that are unwrapped offline. The built-in entries can be
+/// extended from a file, see [`RedirectTable::load`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RedirectTable {
+    redirectors: Vec<Redirector>,
+}
+
+impl RedirectTable {
+    /// Adds the entries of a table file to the built-in ones. Each line holds
+    /// `host path params encoding name`, separated by whitespace, where
+    /// `params` is a comma separated list, `encoding` is `plain`, `base64` or
+    /// `base64+N` to skip N leading characters, and the rest of the line is
+    /// the name shown in the report. Blank lines and `#` comments are skipped.
+    pub fn load(&mut self, path: &Path) -> Result<()> {
+        let text = fs::read_to_string(path).map_err(|source| Error::Config {
+            path: path.to_owned(),
+            message: source.to_string(),
+        })?;
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let redirector = Self::parse_line(line).ok_or_else(|| Error::Config {
+                path: path.to_owned(),
+                message: format!(
+                    "line {}: expected `host path params encoding name`",
+                    number + 1
+                ),
+            })?;
+            self.redirectors.push(redirector);
+        }
+        Ok(())
+    }
+
+    fn parse_line(line: &str) -> Option<Redirector> {
+        let mut fields = line.split_whitespace();
+        let host = fields.next()?.to_ascii_lowercase();
+        let path = fields.next()?.to_string();
+        let params = fields
+            .next()?
+            .split(',')
+            .filter(|p| !p.is_empty())
+            .map(String::from)
+            .collect();
+        let encoding = match fields.next()? {
+            "plain" => ParamEncoding::Plain,
+            "base64" => ParamEncoding::Base64(0),
+            other => ParamEncoding::Base64(other.strip_prefix("base64+")?.parse().ok()?),
+        };
+        let name = fields.collect::<Vec<_>>().join(" ");
+        if name.is_empty() {
+            return None;
+        }
+
+        Some(Redirector {
+            name,
+            host,
+            path,
+            params,
+            encoding,
+        })
+    }
+
+    /// Returns the redirector name and the target embedded in `url`, or
+    /// `None` when no entry matches.
+    pub fn decode(&self, url: &str) -> Option<(String, String)> {
+        let parsed = Url::parse(url).ok()?;
+        let host = parsed.host_str()?.to_ascii_lowercase();
+
+        self.redirectors
+            .iter()
+            .filter(|r| host == r.host || host.ends_with(&format!(".{}", r.host)))
+            .filter(|r| parsed.path().starts_with(&r.path))
+            .find_map(|r| {
+                let value = r
+                    .params
+                    .iter()
+                    .find_map(|p| UrlExtractor::raw_param(&parsed, p))?;
+                let target = match r.encoding {
+                    ParamEncoding::Plain => Self::plain(&value),
+                    ParamEncoding::Base64(skip) => Self::base64(value.get(skip..)?),
+                }?;
+                Some((r.name.to_owned(), target))
+            })
+    }
+
+    /// Percent-decoded value, falling back to base64 for redirectors that
+    /// encode the target without saying so.
+    fn plain(value: &str) -> Option<String> {
+        let decoded = Decode::percent_like(&value.replace('+', " "), b'%');
+        let decoded = String::from_utf8_lossy(&decoded).trim().to_string();
+        if decoded.contains("://") || decoded.to_ascii_lowercase().starts_with("www.") {
+            Some(decoded)
+        } else {
+            Self::base64(value)
+        }
+    }
+
+    fn base64(value: &str) -> Option<String> {
+        let value = String::from_utf8_lossy(&Decode::percent_like(value, b'%')).into_owned();
+        let bytes = LENIENT_BASE64_URL
+            .decode(value.trim_end_matches('='))
+            .or_else(|_| LENIENT_BASE64.decode(value.trim_end_matches('=')))
+            .ok()?;
+        let decoded = String::from_utf8(bytes).ok()?;
+        decoded.contains("://").then_some(decoded)
+    }
+}
+
+impl Default for RedirectTable {
+    fn default() -> Self {
+        let redirectors = Self::BUILT_IN
+            .lines()
+            .filter_map(|line| Self::parse_line(line.trim()))
+            .collect();
+        Self { redirectors }
+    }
+}
+
+impl RedirectTable {
+    /// Built-in entries, in the same format as a table file.
+    const BUILT_IN: &'static str = "
+        google.com                  /url          q,url              plain     Google redirect
+        google.com                  /aclk         adurl              plain     Google Ads click
+        googleadservices.com        /pagead/aclk  adurl              plain     Google Ads click
+        bing.com                    /ck/a         u                  base64+2  Bing click tracking
+        linkedin.com                /redir/       url                plain     LinkedIn redirect
+        l.facebook.com              /l.php        u                  plain     Facebook link shim
+        l.instagram.com             /             u                  plain     Instagram link shim
+        youtube.com                 /redirect     q                  plain     YouTube redirect
+        slack-redir.net             /link         url                plain     Slack redirect
+        clickserve.dartsearch.net   /link/click   ds_dest_url        plain     DoubleClick Search click
+        ad.doubleclick.net          /             adurl,ds_dest_url  plain     DoubleClick ad click
+        t.umblr.com                 /redirect     z                  plain     Tumblr redirect
+        away.vk.com                 /away.php     to                 plain     VK redirect
+    ";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table_file(name: &str, text: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("eml_2_docx_{}_{name}", std::process::id()));
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn parse_line() {
+        let redirector =
+            RedirectTable::parse_line("Go.Example.com /out u,to base64+3 Example out link")
+                .unwrap();
+        assert_eq!(redirector.host, "go.example.com");
+        assert_eq!(redirector.path, "/out");
+        assert_eq!(redirector.params, ["u", "to"]);
+        assert_eq!(redirector.encoding, ParamEncoding::Base64(3));
+        assert_eq!(redirector.name, "Example out link");
+
+        // A name is required and the encoding must be known.
+        assert_eq!(RedirectTable::parse_line("example.com /out u plain"), None);
+        assert_eq!(
+            RedirectTable::parse_line("example.com /out u rot13 Name"),
+            None
+        );
+        assert_eq!(
+            RedirectTable::parse_line("example.com /out u base64+x Name"),
+            None
+        );
+    }
+
+    #[test]
+    fn load() {
+        let path = table_file(
+            "good.txt",
+            "# Extra redirectors\n\n  go.example.com /out u plain Example out link\n",
+        );
+        let mut table = RedirectTable::default();
+        table.load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            table.decode("https://go.example.com/out?u=https%3A%2F%2Fevil.com%2F"),
+            Some((
+                String::from("Example out link"),
+                String::from("https://evil.com/")
+            ))
+        );
+
+        let path = table_file("bad.txt", "# Extra redirectors\ngo.example.com /out\n");
+        let error = RedirectTable::default().load(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(&error, Error::Config { message, .. } if message.starts_with("line 2:")));
+        assert_eq!(error.exit_code(), 7);
+
+        let missing = std::env::temp_dir().join("eml_2_docx_no_such_table.txt");
+        let error = RedirectTable::default().load(&missing).unwrap_err();
+        assert_eq!(error.exit_code(), 7);
+    }
+
+    #[test]
+    fn base64_with_prefix() {
+        let table = RedirectTable::default();
+        assert_eq!(
+            table.decode(
+                "https://www.bing.com/ck/a?!&&p=0123&u=a1aHR0cHM6Ly9leGFtcGxlLmNvbS9wYXRo&ntb=1"
+            ),
+            Some((
+                String::from("Bing click tracking"),
+                String::from("https://example.com/path")
+            ))
+        );
+        // Without skipping the prefix the value is not a URL.
+        assert_eq!(
+            RedirectTable::base64("a1aHR0cHM6Ly9leGFtcGxlLmNvbS9wYXRo"),
+            None
+        );
+    }
+}
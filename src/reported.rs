@@ -0,0 +1,146 @@
+use crate::{
+    email::Body,
+    mime::MimePart,
+    urls::{UrlExtractor, TAG},
+};
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// Details of the wrapper mail when a user reported a suspicious message by
+/// forwarding it as an attachment. The attached message is what gets analyzed,
+/// this keeps who reported it and when.
+#[derive(Debug, Clone)]
+pub struct ReportedMessage {
+    pub format: String,
+    pub reporter: String,
+    pub report_time: String,
+    pub report_subject: String,
+    /// Path of the attached message inside the wrapper's MIME tree.
+    pub inner_path: String,
+}
+
+// Parts of an HTML body that are never shown, Outlook puts a large style
+// block in the head of every mail.
+static HIDDEN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?is)<head\b.*?</head\s*>|<style\b.*?</style\s*>|<script\b.*?</script\s*>")
+        .unwrap()
+});
+
+impl ReportedMessage {
+    /// Looks for a message attached directly to `tree` and returns the
+    /// wrapper details together with the attached message's root part.
+    ///
+    /// Only report wrappers are unwrapped: the Microsoft and Google report
+    /// formats, or a forward that adds nothing but a short note. A mail that
+    /// has its own links or attachments is analyzed itself, with the attached
+    /// message listed as an ordinary attachment.
+    pub fn detect(tree: &MimePart) -> Option<(Self, &MimePart)> {
+        let attached = tree
+            .children
+            .iter()
+            .find(|p| p.is_message() && !p.children.is_empty())?;
+        let inner = &attached.children[0];
+
+        let format = Self::wrapper_format(tree);
+        if format == Self::FORWARDED && !Self::is_bare_wrapper(tree, attached) {
+            return None;
+        }
+
+        let reported = Self {
+            format: format.to_string(),
+            reporter: tree.headers.get("From").unwrap_or("NA").to_string(),
+            report_time: tree.headers.get("Date").unwrap_or("NA").to_string(),
+            report_subject: tree.headers.get("Subject").unwrap_or("NA").to_string(),
+            inner_path: attached.path.to_owned(),
+        };
+        Some((reported, inner))
+    }
+
+    /// True when everything outside `attached` is a short note without links
+    /// or other attachments.
+    fn is_bare_wrapper(tree: &MimePart, attached: &MimePart) -> bool {
+        let nested = format!("{}.", attached.path);
+        let own_parts = tree
+            .flatten()
+            .into_iter()
+            .filter(|p| p.path != attached.path && !p.path.starts_with(&nested));
+
+        for part in own_parts {
+            if part.is_attachment() {
+                return false;
+            }
+            let Some(text) = &part.text else {
+                continue;
+            };
+
+            let body = Body {
+                path: part.path.to_owned(),
+                content_type: part.content_type.to_owned(),
+                text: text.to_owned(),
+            };
+            let mut links = UrlExtractor::new();
+            links.add_body(&body);
+            if !links.into_urls().is_empty() {
+                return false;
+            }
+
+            let visible = if part.content_type == "text/html" {
+                let shown = HIDDEN.replace_all(text, " ");
+                UrlExtractor::html_unescape(&TAG.replace_all(&shown, " "))
+            } else {
+                text.to_owned()
+            };
+            if visible.split_whitespace().map(str::len).sum::<usize>() > Self::MAX_NOTE {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn wrapper_format(tree: &MimePart) -> &'static str {
+        let subject = tree
+            .headers
+            .get("Subject")
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        let has_prefix = |prefixes: &[&str]| {
+            tree.headers.iter().any(|(k, _)| {
+                prefixes
+                    .iter()
+                    .any(|p| k.to_ascii_lowercase().starts_with(p))
+            })
+        };
+
+        let microsoft_subject = Self::MS_SUBJECTS.iter().any(|s| subject.starts_with(s));
+        if microsoft_subject && has_prefix(&Self::MS_HEADERS) {
+            return Self::MICROSOFT;
+        }
+
+        let google_subject = Self::GOOGLE_SUBJECTS.iter().any(|s| subject.contains(s));
+        if google_subject && has_prefix(&Self::GOOGLE_HEADERS) {
+            return Self::GOOGLE;
+        }
+
+        Self::FORWARDED
+    }
+}
+
+impl ReportedMessage {
+    const MICROSOFT: &'static str = "Microsoft Report Message";
+    const GOOGLE: &'static str = "Google Workspace report";
+    const FORWARDED: &'static str = "Forwarded as attachment";
+
+    /// Longest note, in non-whitespace bytes, a plain forward may add before
+    /// the wrapper counts as a mail of its own.
+    const MAX_NOTE: usize = 500;
+
+    // Microsoft's Report Message add-in and the built-in report button prefix
+    // the subject with the verdict the user picked.
+    const MS_SUBJECTS: [&'static str; 5] = ["phish:", "phishing:", "junk:", "not junk:", "spam:"];
+    const MS_HEADERS: [&'static str; 2] = ["x-ms-exchange-", "x-microsoft-antispam"];
+
+    const GOOGLE_SUBJECTS: [&'static str; 3] =
+        ["report phishing", "reported phishing", "spam report"];
+    const GOOGLE_HEADERS: [&'static str; 2] = ["x-google-", "x-gm-"];
+}
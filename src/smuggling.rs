@@ -0,0 +1,247 @@
+use crate::{
+    decode::{Decode, LENIENT_BASE64},
+    filetype::FileType,
+};
+use base64::Engine;
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// An HTML part that builds a file in the browser instead of attaching it,
+/// which keeps the payload away from attachment scanning.
+#[derive(Debug, Clone)]
+pub struct Smuggling {
+    /// MIME path of the HTML body or attachment.
+    pub path: String,
+    /// The building blocks found, such as `new Blob` or a download link with
+    /// a `data:` URI.
+    pub techniques: Vec<String>,
+    /// Files recovered from the page without running it.
+    pub payloads: Vec<SmuggledPayload>,
+}
+
+/// A file recovered from a smuggling page.
+#[derive(Debug, Clone)]
+pub struct SmuggledPayload {
+    /// Name the page saves the file under, when it gives one.
+    pub filename: Option<String>,
+    /// Type declared by a `data:` URI.
+    pub content_type: Option<String>,
+    pub data: Vec<u8>,
+}
+
+static BLOB: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\bnew\s+Blob\s*\(").unwrap());
+
+static MS_SAVE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"\bmsSave(?:OrOpen)?Blob\s*\(\s*[^,()]+,\s*["'`]([^"'`]+)["'`]|\bmsSave(?:OrOpen)?Blob\b"#)
+        .unwrap()
+});
+
+static OBJECT_URL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\bURL\s*\.\s*createObjectURL\s*\(").unwrap());
+
+static DOWNLOAD_PROPERTY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"\.download\s*=\s*["'`]([^"'`]+)["'`]|setAttribute\s*\(\s*["']download["']\s*,\s*["'`]([^"'`]+)["'`]"#)
+        .unwrap()
+});
+
+static ANCHOR: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?is)<a\b[^>]*>").unwrap());
+
+static ATTRIBUTE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?is)\b(download|href)\b(?:\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>]+)))?"#).unwrap()
+});
+
+static DATA_URI: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?is)^data:([^;,]*)((?:;[^;,]*)*),(.*)$"#).unwrap());
+
+// A data: URI written as a string in a script.
+static SCRIPT_DATA_URI: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"["'`](data:[^"'`]{64,})["'`]"#).unwrap());
+
+// Adjacent string literals joined with `+`, removed before looking for
+// base64 so that payloads split over many lines come out whole.
+static CONCAT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"["'`]\s*\+\s*["'`]"#).unwrap());
+
+static BASE64_LITERAL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r#"["'`]([A-Za-z0-9+/\r\n\\]{{{},}}={{0,2}})["'`]"#,
+        Smuggling::MIN_BASE64
+    ))
+    .unwrap()
+});
+
+static BYTE_ARRAY: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[\s*((?:\d{1,3}\s*,\s*){63,}\d{1,3})\s*,?\s*\]").unwrap());
+
+impl Smuggling {
+    /// Looks for smuggling in the HTML of one part, `None` when there is no
+    /// sign of a file being built client-side.
+    pub fn find(path: &str, html: &str) -> Option<Smuggling> {
+        let mut found = Smuggling {
+            path: path.to_string(),
+            techniques: Vec::new(),
+            payloads: Vec::new(),
+        };
+        let mut names = Vec::new();
+
+        let blob = BLOB.is_match(html);
+        if blob {
+            found.techniques.push(String::from("Blob built in script"));
+        }
+        if let Some(cap) = MS_SAVE.captures(html) {
+            found
+                .techniques
+                .push(String::from("saved with navigator.msSaveOrOpenBlob"));
+            names.extend(cap.get(1).map(|m| m.as_str().to_string()));
+        }
+        let object_url = OBJECT_URL.is_match(html);
+        if object_url {
+            found.techniques.push(String::from("URL.createObjectURL"));
+        }
+        let mut downloads = DOWNLOAD_PROPERTY.captures_iter(html).peekable();
+        if downloads.peek().is_some() {
+            found
+                .techniques
+                .push(String::from("download name set by script"));
+        }
+        for cap in downloads {
+            let name = cap.get(1).or_else(|| cap.get(2)).unwrap().as_str();
+            names.push(name.to_string());
+        }
+
+        found.download_links(html, &mut names);
+
+        let scripted = blob && (object_url || !names.is_empty())
+            || found.techniques.iter().any(|t| t.starts_with("saved with"));
+        if scripted {
+            for cap in SCRIPT_DATA_URI.captures_iter(html) {
+                if let Some(payload) = Self::data_uri(&cap[1]) {
+                    found.add(payload);
+                }
+            }
+        }
+
+        // Large encoded blobs are kept when the page builds a file, or when
+        // they decode to something that is clearly not a web page asset.
+        let joined = CONCAT.replace_all(html, "");
+        let mut large = 0;
+        for cap in BASE64_LITERAL.captures_iter(&joined) {
+            let encoded: String = cap[1]
+                .chars()
+                .filter(|c| !c.is_whitespace() && *c != '\\')
+                .collect();
+            let Ok(data) = LENIENT_BASE64.decode(&encoded) else {
+                continue;
+            };
+            large += 1;
+            if scripted || Self::is_file(&data) {
+                found.add(SmuggledPayload {
+                    filename: None,
+                    content_type: None,
+                    data,
+                });
+            }
+        }
+        if large > 0 {
+            found
+                .techniques
+                .push(format!("{large} large base64 string(s)"));
+        }
+        if scripted {
+            for cap in BYTE_ARRAY.captures_iter(html) {
+                let data: Option<Vec<u8>> = cap[1]
+                    .split(',')
+                    .map(|n| n.trim().parse::<u8>().ok())
+                    .collect();
+                if let Some(data) = data {
+                    found.add(SmuggledPayload {
+                        filename: None,
+                        content_type: None,
+                        data,
+                    });
+                }
+            }
+        }
+
+        // A single name belongs to the single payload the page saves.
+        if let ([name], [payload]) = (names.as_slice(), found.payloads.as_mut_slice()) {
+            payload.filename.get_or_insert_with(|| name.to_owned());
+        }
+
+        let smuggling = scripted || !found.payloads.is_empty();
+        smuggling.then_some(found)
+    }
+
+    /// `<a download>` links whose target is a `data:` URI carry the whole
+    /// file in the link itself.
+    fn download_links(&mut self, html: &str, names: &mut Vec<String>) {
+        for tag in ANCHOR.find_iter(html) {
+            let mut download = None;
+            let mut href = None;
+            for cap in ATTRIBUTE.captures_iter(tag.as_str()) {
+                let value = cap
+                    .get(2)
+                    .or_else(|| cap.get(3))
+                    .or_else(|| cap.get(4))
+                    .map_or("", |m| m.as_str());
+                if cap[1].eq_ignore_ascii_case("download") {
+                    download = Some(value.to_string());
+                } else {
+                    href = Some(value.to_string());
+                }
+            }
+            let (Some(download), Some(href)) = (download, href) else {
+                continue;
+            };
+            let Some(mut payload) = Self::data_uri(href.trim()) else {
+                continue;
+            };
+            let technique = String::from("download link with a data: URI");
+            if !self.techniques.contains(&technique) {
+                self.techniques.push(technique);
+            }
+            if !download.is_empty() {
+                payload.filename = Some(download.to_owned());
+                names.push(download);
+            }
+            self.add(payload);
+        }
+    }
+
+    fn data_uri(uri: &str) -> Option<SmuggledPayload> {
+        let cap = DATA_URI.captures(uri)?;
+        let base64 = cap[2].to_ascii_lowercase().contains(";base64");
+        let data = if base64 {
+            let encoded: String = cap[3].chars().filter(|c| !c.is_whitespace()).collect();
+            LENIENT_BASE64.decode(encoded).ok()?
+        } else {
+            Decode::percent_like(&cap[3], b'%')
+        };
+        let content_type = cap[1].trim().to_ascii_lowercase();
+        Some(SmuggledPayload {
+            filename: None,
+            content_type: (!content_type.is_empty()).then_some(content_type),
+            data,
+        })
+    }
+
+    fn add(&mut self, payload: SmuggledPayload) {
+        if payload.data.len() >= Self::MIN_PAYLOAD
+            && !self.payloads.iter().any(|p| p.data == payload.data)
+        {
+            self.payloads.push(payload);
+        }
+    }
+
+    /// Executables, archives, documents and disk images, but not images or
+    /// fonts a page may legitimately embed.
+    fn is_file(data: &[u8]) -> bool {
+        FileType::detect(data)
+            .is_some_and(|t| !t.mime_types.iter().any(|m| m.starts_with("image/")))
+    }
+}
+
+impl Smuggling {
+    /// Shorter base64 strings are too common in ordinary scripts.
+    const MIN_BASE64: usize = 1024;
+    const MIN_PAYLOAD: usize = 64;
+}
@@ -0,0 +1,309 @@
+use crate::{email::Body, gateway::Gateway, redirect::RedirectTable};
+use regex::Regex;
+use std::sync::LazyLock;
+use url::Url;
+
+/// A unique URL found in the message, with every place it was seen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtractedUrl {
+    pub url: String,
+    pub sources: Vec<String>,
+    /// Targets behind rewritten links, outermost first. Empty when the URL
+    /// is not wrapped.
+    pub unwrapped: Vec<UnwrappedUrl>,
+}
+
+/// One layer removed from a rewritten link, with the service that wrapped it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnwrappedUrl {
+    pub via: String,
+    pub url: String,
+}
+
+impl ExtractedUrl {
+    /// Where the link finally leads once every known wrapper is removed.
+    pub fn destination(&self) -> &str {
+        self.unwrapped.last().map_or(&self.url, |u| &u.url)
+    }
+}
+
+/// Finds URLs in text and HTML bodies: link targets, image sources, form
+/// actions and bare URLs in the text.
+pub struct UrlExtractor {
+    urls: Vec<ExtractedUrl>,
+    redirects: RedirectTable,
+}
+
+static ATTRIBUTE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"(?is)\b(href|src|action|formaction|background|poster|data-href|data-src)\s*=\s*("[^"]*"|'[^']*'|[^\s>]+)"#,
+    )
+    .unwrap()
+});
+
+static BARE_URL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)\b(?:(?:https?|ftp)://|www\.)[^\s<>"'`{}|\\^\[\]]+"#).unwrap()
+});
+
+/// Any HTML tag, for reducing markup to its text.
+pub(crate) static TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?s)<[^>]*>").unwrap());
+
+static META_REFRESH: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)content\s*=\s*["']?\s*\d+\s*;\s*url\s*=\s*([^"'>\s]+)"#).unwrap()
+});
+
+impl UrlExtractor {
+    pub fn new() -> Self {
+        Self::with_redirects(RedirectTable::default())
+    }
+
+    pub fn with_redirects(redirects: RedirectTable) -> Self {
+        Self {
+            urls: Vec::new(),
+            redirects,
+        }
+    }
+
+    pub fn extract(bodies: &[Body], redirects: &RedirectTable) -> Vec<ExtractedUrl> {
+        let mut extractor = Self::with_redirects(redirects.clone());
+        for body in bodies {
+            extractor.add_body(body);
+        }
+        extractor.into_urls()
+    }
+
+    pub fn add_body(&mut self, body: &Body) {
+        let part = if body.path.is_empty() {
+            String::from("message")
+        } else {
+            format!("part {}", body.path)
+        };
+
+        if body.content_type == "text/html" {
+            for cap in ATTRIBUTE.captures_iter(&body.text) {
+                let value = cap[2].trim_matches(|c| c == '"' || c == '\'');
+                let source = format!("{} in {part}", cap[1].to_ascii_lowercase());
+                self.add(&Self::html_unescape(value), &source);
+            }
+            for cap in META_REFRESH.captures_iter(&body.text) {
+                self.add(
+                    &Self::html_unescape(&cap[1]),
+                    &format!("meta refresh in {part}"),
+                );
+            }
+        }
+
+        // Bare URLs, for HTML only the visible text is searched since the
+        // attributes were handled above.
+        let text = if body.content_type == "text/html" {
+            Self::html_unescape(&TAG.replace_all(&body.text, " "))
+        } else {
+            body.text.to_owned()
+        };
+        for m in BARE_URL.find_iter(&text) {
+            self.add(m.as_str(), &format!("text in {part}"));
+        }
+    }
+
+    /// Adds a URL found outside the message bodies, such as in an attachment.
+    pub fn add(&mut self, raw: &str, source: &str) {
+        let url = match Self::normalize(raw) {
+            Some(url) => url,
+            None => return,
+        };
+
+        match self.urls.iter_mut().find(|u| u.url == url) {
+            Some(existing) => {
+                if !existing.sources.iter().any(|s| s == source) {
+                    existing.sources.push(source.to_string());
+                }
+            }
+            None => self.urls.push(ExtractedUrl {
+                unwrapped: self.unwrap(&url),
+                url,
+                sources: vec![source.to_string()],
+            }),
+        }
+    }
+
+    /// Peels off gateway rewrites and redirectors one layer at a time, a
+    /// Safe Links URL may well wrap a Google redirect to the real target.
+    pub fn unwrap(&self, url: &str) -> Vec<UnwrappedUrl> {
+        let mut chain: Vec<UnwrappedUrl> = Vec::new();
+        let mut current = url.to_string();
+        while chain.len() < Self::MAX_UNWRAP {
+            let decoded = Gateway::decode(&current)
+                .map(|(via, target)| (via.to_string(), target))
+                .or_else(|| self.redirects.decode(&current));
+            let (via, target) = match decoded {
+                Some(decoded) => decoded,
+                None => break,
+            };
+            let target = Self::normalize(&target).unwrap_or(target);
+            if target == current || chain.iter().any(|u| u.url == target) {
+                break;
+            }
+            chain.push(UnwrappedUrl {
+                via,
+                url: target.to_owned(),
+            });
+            current = target;
+        }
+        chain
+    }
+
+    pub fn into_urls(self) -> Vec<ExtractedUrl> {
+        self.urls
+    }
+
+    /// Trims punctuation picked up from the surrounding text, adds a scheme
+    /// to `www.` links and lowercases the scheme and host. Anything that is
+    /// not a web or ftp URL (mailto:, cid:, anchors) is dropped.
+    pub fn normalize(raw: &str) -> Option<String> {
+        let mut candidate = raw
+            .trim()
+            .trim_end_matches(['.', ',', ';', ':', '!', '?', '\'', '"']);
+        // Closing brackets belong to the URL only if it also opened them.
+        while let Some(stripped) = candidate.strip_suffix(')') {
+            if candidate.matches('(').count() >= candidate.matches(')').count() {
+                break;
+            }
+            candidate = stripped;
+        }
+
+        let with_scheme = if candidate.to_ascii_lowercase().starts_with("www.") {
+            format!("http://{candidate}")
+        } else {
+            candidate.to_string()
+        };
+
+        let url = Url::parse(&with_scheme).ok()?;
+        if !matches!(url.scheme(), "http" | "https" | "ftp") || url.host_str().is_none() {
+            return None;
+        }
+        Some(url.to_string())
+    }
+
+    /// Value of the first query parameter called `name`, compared without
+    /// case, exactly as it appears in the URL. Empty values count as missing.
+    pub(crate) fn raw_param(url: &Url, name: &str) -> Option<String> {
+        url.query()?
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.to_string())
+            .filter(|value| !value.is_empty())
+    }
+
+    pub fn html_unescape(text: &str) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(amp) = rest.find('&') {
+            out.push_str(&rest[..amp]);
+            rest = &rest[amp..];
+            let end = match rest.as_bytes()[..rest.len().min(12)]
+                .iter()
+                .position(|b| *b == b';')
+            {
+                Some(end) => end,
+                None => {
+                    out.push('&');
+                    rest = &rest[1..];
+                    continue;
+                }
+            };
+            let entity = &rest[1..end];
+            let decoded = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                _ => entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                    .and_then(|h| u32::from_str_radix(h, 16).ok())
+                    .or_else(|| entity.strip_prefix('#').and_then(|d| d.parse().ok()))
+                    .and_then(char::from_u32),
+            };
+            match decoded {
+                Some(c) => {
+                    out.push(c);
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    out.push('&');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+impl UrlExtractor {
+    const MAX_UNWRAP: usize = 8;
+}
+
+impl Default for UrlExtractor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize() {
+        assert_eq!(
+            UrlExtractor::normalize("https://Example.COM/a.").as_deref(),
+            Some("https://example.com/a")
+        );
+        assert_eq!(
+            UrlExtractor::normalize("https://example.com/a?b=c\",").as_deref(),
+            Some("https://example.com/a?b=c")
+        );
+        assert_eq!(
+            UrlExtractor::normalize("www.example.com/login").as_deref(),
+            Some("http://www.example.com/login")
+        );
+        assert_eq!(UrlExtractor::normalize("mailto:a@example.com"), None);
+        assert_eq!(UrlExtractor::normalize("cid:image001.png"), None);
+    }
+
+    #[test]
+    fn normalize_keeps_balanced_brackets() {
+        // Text such as "(see https://example.com/a)" leaves a stray bracket.
+        assert_eq!(
+            UrlExtractor::normalize("https://example.com/a)").as_deref(),
+            Some("https://example.com/a")
+        );
+        assert_eq!(
+            UrlExtractor::normalize("https://en.wikipedia.org/wiki/Bank_(finance)").as_deref(),
+            Some("https://en.wikipedia.org/wiki/Bank_(finance)")
+        );
+        assert_eq!(
+            UrlExtractor::normalize("https://en.wikipedia.org/wiki/Bank_(finance)).").as_deref(),
+            Some("https://en.wikipedia.org/wiki/Bank_(finance)")
+        );
+    }
+
+    #[test]
+    fn html_unescape() {
+        assert_eq!(
+            UrlExtractor::html_unescape("a?b=1&amp;c=2&lt;&gt;&quot;&apos;"),
+            "a?b=1&c=2<>\"'"
+        );
+        assert_eq!(UrlExtractor::html_unescape("&#104;&#x74;&#X74;p"), "http");
+        // Unknown or unterminated entities are kept as they are.
+        assert_eq!(
+            UrlExtractor::html_unescape("a&copy=1&b=2&unknown;"),
+            "a&copy=1&b=2&unknown;"
+        );
+        assert_eq!(UrlExtractor::html_unescape("AT&T"), "AT&T");
+    }
+}